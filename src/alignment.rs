@@ -0,0 +1,703 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{MyteError, Result};
+use crate::input::InputFmt;
+
+const DEFAULT_MISSING: char = '?';
+const DEFAULT_GAP: char = '-';
+// IUPAC nucleotide codes, missing data and gaps
+const DNA_CHARS: &str = "ACGTUNRYKMSWBDHV?-.";
+// Amino acids, ambiguity codes, stop codons, missing data and gaps
+const PROTEIN_CHARS: &str = "ACDEFGHIKLMNPQRSTVWYBZJXUO*?-.";
+const STANDARD_CHARS: &str = "0123456789?-.";
+// Taxon names in strict PHYLIP take exactly this many characters
+pub const PHYLIP_STRICT_NAME_LEN: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Dna,
+    Rna,
+    Protein,
+    Standard,
+}
+
+impl DataType {
+    // Symbols are uppercase. Lowercase sequences are valid.
+    pub fn symbols(&self) -> &'static str {
+        match self {
+            DataType::Dna | DataType::Rna => DNA_CHARS,
+            DataType::Protein => PROTEIN_CHARS,
+            DataType::Standard => STANDARD_CHARS,
+        }
+    }
+}
+
+// An alignment in memory. Sequences are stored
+// in the same order as the taxa in the input file.
+#[derive(Debug, Clone)]
+pub struct Alignment {
+    pub path: PathBuf,
+    pub taxa: Vec<String>,
+    pub sequences: Vec<String>,
+    pub datatype: DataType,
+    pub missing: char,
+    pub gap: char,
+}
+
+impl Alignment {
+    pub fn from_file(path: &Path, input_fmt: &InputFmt) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|err| MyteError::io(path, err))?;
+        Self::parse(path, &content, input_fmt)
+    }
+
+    pub fn parse(path: &Path, content: &str, input_fmt: &InputFmt) -> Result<Self> {
+        match input_fmt {
+            InputFmt::Auto => match InputFmt::detect(content) {
+                Some(fmt) => Self::parse(path, content, &fmt),
+                None => Err(MyteError::Parse {
+                    path: path.to_path_buf(),
+                    line: None,
+                    message: String::from("Unknown alignment format"),
+                }),
+            },
+            InputFmt::Fasta => FastaReader::new(path).parse(content),
+            InputFmt::Nexus => NexusReader::new(path).parse(content),
+            InputFmt::Phylip => PhylipReader::new(path).parse(content),
+            InputFmt::PhylipStrict => PhylipReader::strict(path).parse(content),
+        }
+    }
+
+    pub fn ntax(&self) -> usize {
+        self.taxa.len()
+    }
+
+    // Alignments are checked for equal lengths when parsed.
+    pub fn nchar(&self) -> usize {
+        self.sequences.first().map(|seq| seq.len()).unwrap_or(0)
+    }
+
+    // Gaps, missing data, and fully ambiguous states
+    pub fn is_missing(&self, c: char) -> bool {
+        let unknown = match self.datatype {
+            DataType::Dna | DataType::Rna => 'N',
+            DataType::Protein => 'X',
+            DataType::Standard => '?',
+        };
+        c == self.missing || c == self.gap || "?-.".contains(c) || c.to_ascii_uppercase() == unknown
+    }
+
+    pub fn get_sequence(&self, taxon: &str) -> Option<&str> {
+        self.taxa
+            .iter()
+            .position(|name| name == taxon)
+            .map(|i| self.sequences[i].as_str())
+    }
+}
+
+// Collects sequences while a file is parsed.
+// Lines are kept to point errors to the taxon.
+struct Records<'a> {
+    path: &'a Path,
+    taxa: Vec<String>,
+    sequences: Vec<String>,
+    lines: Vec<usize>,
+    index: HashMap<String, usize>,
+}
+
+impl<'a> Records<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            taxa: Vec::new(),
+            sequences: Vec::new(),
+            lines: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, taxon: &str, line: usize) -> Result<()> {
+        if taxon.is_empty() {
+            return Err(parse_error(self.path, line, "Missing taxon name"));
+        }
+        if self.index.contains_key(taxon) {
+            let msg = format!("Duplicate taxon name {}", taxon);
+            return Err(parse_error(self.path, line, &msg));
+        }
+        self.index.insert(String::from(taxon), self.taxa.len());
+        self.taxa.push(String::from(taxon));
+        self.sequences.push(String::new());
+        self.lines.push(line);
+        Ok(())
+    }
+
+    // Interleaved files append to the taxon by name.
+    fn append(&mut self, taxon: &str, seq: &str, line: usize) -> Result<()> {
+        if !self.index.contains_key(taxon) {
+            self.insert(taxon, line)?;
+        }
+        let i = self.index[taxon];
+        self.append_at(i, seq, line);
+        Ok(())
+    }
+
+    fn append_at(&mut self, i: usize, seq: &str, line: usize) {
+        self.sequences[i].extend(seq.chars().filter(|c| !c.is_whitespace()));
+        self.lines[i] = line;
+    }
+
+    fn check_counts(&self, ntax: Option<usize>, nchar: Option<usize>, line: usize) -> Result<()> {
+        if self.taxa.is_empty() {
+            return Err(MyteError::Parse {
+                path: self.path.to_path_buf(),
+                line: None,
+                message: String::from("Found no sequences"),
+            });
+        }
+        if let Some(ntax) = ntax {
+            if ntax != self.taxa.len() {
+                let msg = format!("Expected {} taxa, found {}", ntax, self.taxa.len());
+                return Err(parse_error(self.path, line, &msg));
+            }
+        }
+        let expected = nchar.unwrap_or_else(|| self.sequences[0].len());
+        match self.sequences.iter().position(|seq| seq.len() != expected) {
+            Some(i) => {
+                let msg = format!(
+                    "Sequence length of {} is {}, expected {}",
+                    self.taxa[i],
+                    self.sequences[i].len(),
+                    expected
+                );
+                Err(parse_error(self.path, self.lines[i], &msg))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn into_alignment(self, datatype: Option<DataType>, missing: char, gap: char) -> Alignment {
+        let datatype = datatype.unwrap_or_else(|| infer_datatype(&self.sequences));
+        Alignment {
+            path: self.path.to_path_buf(),
+            taxa: self.taxa,
+            sequences: self.sequences,
+            datatype,
+            missing,
+            gap,
+        }
+    }
+}
+
+struct FastaReader<'a> {
+    path: &'a Path,
+}
+
+impl<'a> FastaReader<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self { path }
+    }
+
+    fn parse(&self, content: &str) -> Result<Alignment> {
+        let mut records = Records::new(self.path);
+        let mut last_line = 0;
+        for (i, line) in content.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            last_line = line_num;
+            match line.strip_prefix('>') {
+                Some(name) => records.insert(name.trim(), line_num)?,
+                None => {
+                    if records.taxa.is_empty() {
+                        let msg = "Found a sequence before the first header";
+                        return Err(parse_error(self.path, line_num, msg));
+                    }
+                    let last = records.taxa.len() - 1;
+                    records.append_at(last, line, line_num);
+                }
+            }
+        }
+        records.check_counts(None, None, last_line)?;
+        Ok(records.into_alignment(None, DEFAULT_MISSING, DEFAULT_GAP))
+    }
+}
+
+// Supports PHYLIP, both sequential and interleaved.
+// Relaxed names are separated from sequences by whitespace.
+// Strict names take the first 10 characters of the line.
+struct PhylipReader<'a> {
+    path: &'a Path,
+    strict: bool,
+}
+
+impl<'a> PhylipReader<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            strict: false,
+        }
+    }
+
+    fn strict(path: &'a Path) -> Self {
+        Self { path, strict: true }
+    }
+
+    fn parse(&self, content: &str) -> Result<Alignment> {
+        // Leading spaces are part of strict names.
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim_end()))
+            .filter(|(_, line)| !line.trim().is_empty());
+        let (header_line, header) = match lines.next() {
+            Some(header) => header,
+            None => return Err(parse_error(self.path, 1, "Missing PHYLIP header")),
+        };
+        let (ntax, nchar) = self.parse_header(header, header_line)?;
+        let lines: Vec<(usize, &str)> = lines.collect();
+        let records = match self.parse_sequential(&lines, ntax, nchar) {
+            Some(records) => records,
+            None => self.parse_interleaved(&lines, ntax)?,
+        };
+        records.check_counts(Some(ntax), Some(nchar), header_line)?;
+        Ok(records.into_alignment(None, DEFAULT_MISSING, DEFAULT_GAP))
+    }
+
+    // Sequential files fill each taxon up to nchar before the next
+    // taxon starts. Returns None when the lines do not fit that layout,
+    // so they are read as interleaved.
+    fn parse_sequential(
+        &self,
+        lines: &[(usize, &str)],
+        ntax: usize,
+        nchar: usize,
+    ) -> Option<Records<'a>> {
+        let mut records = Records::new(self.path);
+        for &(line_num, line) in lines {
+            let is_filled = records
+                .sequences
+                .last()
+                .map_or(true, |seq| seq.len() >= nchar);
+            if is_filled {
+                let (name, seq) = self.split_name(line);
+                records.insert(&name, line_num).ok()?;
+                records.append_at(records.taxa.len() - 1, seq, line_num);
+            } else {
+                records.append_at(records.taxa.len() - 1, line, line_num);
+            }
+            if records.sequences.last()?.len() > nchar {
+                return None;
+            }
+        }
+        let is_complete =
+            records.taxa.len() == ntax && records.sequences.iter().all(|seq| seq.len() == nchar);
+        if is_complete {
+            Some(records)
+        } else {
+            None
+        }
+    }
+
+    // Interleaved blocks list the taxa in the same order,
+    // and only the first block has names.
+    fn parse_interleaved(&self, lines: &[(usize, &str)], ntax: usize) -> Result<Records<'a>> {
+        let mut records = Records::new(self.path);
+        for (row, &(line_num, line)) in lines.iter().enumerate() {
+            if row < ntax {
+                let (name, seq) = self.split_name(line);
+                records.insert(&name, line_num)?;
+                records.append_at(row, seq, line_num);
+            } else {
+                records.append_at(row % ntax, line, line_num);
+            }
+        }
+        Ok(records)
+    }
+
+    fn split_name<'l>(&self, line: &'l str) -> (Cow<'l, str>, &'l str) {
+        if !self.strict {
+            return split_name(line.trim());
+        }
+        match line.char_indices().nth(PHYLIP_STRICT_NAME_LEN) {
+            Some((i, _)) => (Cow::Borrowed(line[..i].trim()), &line[i..]),
+            None => (Cow::Borrowed(line.trim()), ""),
+        }
+    }
+
+    fn parse_header(&self, header: &str, line: usize) -> Result<(usize, usize)> {
+        // Flags after the counts are ignored.
+        let counts: Vec<usize> = header
+            .split_whitespace()
+            .map_while(|value| value.parse().ok())
+            .collect();
+        match counts[..] {
+            [ntax, nchar, ..] if ntax > 0 => Ok((ntax, nchar)),
+            _ => Err(parse_error(
+                self.path,
+                line,
+                "Invalid PHYLIP header. Expected the number of taxa and characters",
+            )),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum NexusState {
+    Outside,
+    Data,
+    Matrix,
+}
+
+// Reads the first data or characters block.
+// Other blocks are skipped. Statements are expected to be on a single line,
+// except the matrix.
+struct NexusReader<'a> {
+    path: &'a Path,
+    state: NexusState,
+    in_comment: bool,
+    ntax: Option<usize>,
+    nchar: Option<usize>,
+    dimensions_line: usize,
+    datatype: Option<DataType>,
+    missing: char,
+    gap: char,
+    has_matrix: bool,
+}
+
+impl<'a> NexusReader<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            state: NexusState::Outside,
+            in_comment: false,
+            ntax: None,
+            nchar: None,
+            dimensions_line: 0,
+            datatype: None,
+            missing: DEFAULT_MISSING,
+            gap: DEFAULT_GAP,
+            has_matrix: false,
+        }
+    }
+
+    fn parse(mut self, content: &str) -> Result<Alignment> {
+        let mut records = Records::new(self.path);
+        let mut has_header = false;
+        for (i, line) in content.lines().enumerate() {
+            let line_num = i + 1;
+            let line = self.strip_comments(line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if !has_header {
+                if !line.to_lowercase().starts_with("#nexus") {
+                    return Err(parse_error(self.path, line_num, "Missing #NEXUS header"));
+                }
+                has_header = true;
+                continue;
+            }
+            match self.state {
+                NexusState::Outside => self.parse_outside(line),
+                NexusState::Data => self.parse_data(line, line_num, &mut records)?,
+                NexusState::Matrix => self.parse_matrix(line, line_num, &mut records)?,
+            }
+            if self.has_matrix && self.state == NexusState::Outside {
+                break;
+            }
+        }
+        if self.state == NexusState::Matrix {
+            return Err(parse_error(
+                self.path,
+                content.lines().count(),
+                "Unexpected end of file in the matrix",
+            ));
+        }
+        if !self.has_matrix {
+            return Err(MyteError::Parse {
+                path: self.path.to_path_buf(),
+                line: None,
+                message: String::from("Missing the data matrix"),
+            });
+        }
+        records.check_counts(self.ntax, self.nchar, self.dimensions_line)?;
+        Ok(records.into_alignment(self.datatype, self.missing, self.gap))
+    }
+
+    // Comments can span multiple lines.
+    fn strip_comments(&mut self, line: &str) -> String {
+        let mut text = String::with_capacity(line.len());
+        for c in line.chars() {
+            match c {
+                '[' => self.in_comment = true,
+                ']' if self.in_comment => self.in_comment = false,
+                _ if !self.in_comment => text.push(c),
+                _ => (),
+            }
+        }
+        text
+    }
+
+    fn parse_outside(&mut self, line: &str) {
+        let line = line.to_lowercase();
+        if line.starts_with("begin data") || line.starts_with("begin characters") {
+            self.state = NexusState::Data;
+        }
+    }
+
+    fn parse_data(&mut self, line: &str, line_num: usize, records: &mut Records) -> Result<()> {
+        let lower = line.to_lowercase();
+        if lower.starts_with("dimensions") {
+            self.dimensions_line = line_num;
+            self.parse_dimensions(&lower, line_num)?;
+        } else if lower.starts_with("format") {
+            // Symbols are case sensitive
+            self.parse_format(line, line_num)?;
+        } else if lower.starts_with("matrix") {
+            self.state = NexusState::Matrix;
+            self.has_matrix = true;
+            let rest = line["matrix".len()..].trim();
+            if !rest.is_empty() {
+                self.parse_matrix(rest, line_num, records)?;
+            }
+        } else if lower.starts_with("end") {
+            self.state = NexusState::Outside;
+        }
+        Ok(())
+    }
+
+    fn parse_dimensions(&mut self, line: &str, line_num: usize) -> Result<()> {
+        for (key, value) in parse_options(line) {
+            let count = || {
+                value.parse::<usize>().map_err(|_| {
+                    let msg = format!("Invalid {} value: {}", key, value);
+                    parse_error(self.path, line_num, &msg)
+                })
+            };
+            match key.as_str() {
+                "ntax" => self.ntax = Some(count()?),
+                "nchar" => self.nchar = Some(count()?),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_format(&mut self, line: &str, line_num: usize) -> Result<()> {
+        for (key, value) in parse_options(line) {
+            match key.to_lowercase().as_str() {
+                "datatype" => {
+                    let value = value.to_lowercase();
+                    self.datatype = Some(self.parse_datatype(&value, line_num)?)
+                }
+                "missing" => self.missing = self.parse_symbol(&key, &value, line_num)?,
+                "gap" => self.gap = self.parse_symbol(&key, &value, line_num)?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_datatype(&self, value: &str, line_num: usize) -> Result<DataType> {
+        match value {
+            "dna" | "nucleotide" => Ok(DataType::Dna),
+            "rna" => Ok(DataType::Rna),
+            "protein" => Ok(DataType::Protein),
+            "standard" => Ok(DataType::Standard),
+            _ => {
+                let msg = format!("Unsupported datatype {}", value);
+                Err(parse_error(self.path, line_num, &msg))
+            }
+        }
+    }
+
+    fn parse_symbol(&self, key: &str, value: &str, line_num: usize) -> Result<char> {
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => {
+                let msg = format!("Invalid {} symbol: {}", key, value);
+                Err(parse_error(self.path, line_num, &msg))
+            }
+        }
+    }
+
+    fn parse_matrix(&mut self, line: &str, line_num: usize, records: &mut Records) -> Result<()> {
+        let (line, is_end) = match line.strip_suffix(';') {
+            Some(line) => (line.trim(), true),
+            None => (line, false),
+        };
+        if !line.is_empty() {
+            let (name, seq) = split_name(line);
+            if seq.is_empty() {
+                let msg = format!("Missing sequence for {}", name);
+                return Err(parse_error(self.path, line_num, &msg));
+            }
+            records.append(&name, seq, line_num)?;
+        }
+        if is_end {
+            self.state = NexusState::Data;
+        }
+        Ok(())
+    }
+}
+
+// Splits `key=value` pairs. Spaces around `=` are allowed.
+fn parse_options(statement: &str) -> Vec<(String, String)> {
+    let statement = statement.trim_end_matches(';').replace(" =", "=");
+    let statement = statement.replace("= ", "=");
+    statement
+        .split_whitespace()
+        .filter_map(|option| {
+            let mut parts = option.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((String::from(key), String::from(value))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Taxon names can be quoted when they contain spaces.
+// Quotes within a quoted name are doubled.
+fn split_name(line: &str) -> (Cow<'_, str>, &str) {
+    if let Some(rest) = line.strip_prefix('\'') {
+        let mut name = String::new();
+        let mut chars = rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let is_end = c == '\'' && chars.next_if(|&(_, next)| next == '\'').is_none();
+            if is_end {
+                return (Cow::Owned(name), rest[i + 1..].trim());
+            }
+            name.push(c);
+        }
+    }
+    match line.find(char::is_whitespace) {
+        Some(i) => (Cow::Borrowed(&line[..i]), line[i..].trim()),
+        None => (Cow::Borrowed(line), ""),
+    }
+}
+
+fn infer_datatype(sequences: &[String]) -> DataType {
+    let is_dna = sequences
+        .iter()
+        .flat_map(|seq| seq.chars())
+        .all(|c| DNA_CHARS.contains(c.to_ascii_uppercase()));
+    if is_dna {
+        DataType::Dna
+    } else {
+        DataType::Protein
+    }
+}
+
+fn parse_error(path: &Path, line: usize, message: &str) -> MyteError {
+    MyteError::Parse {
+        path: path.to_path_buf(),
+        line: Some(line),
+        message: String::from(message),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nexus_test() {
+        let path = Path::new("test_files/gene_1.nexus");
+        let aln = Alignment::from_file(path, &InputFmt::Nexus).unwrap();
+        assert_eq!(39, aln.ntax());
+        assert_eq!(410, aln.nchar());
+        assert_eq!(DataType::Dna, aln.datatype);
+        assert_eq!('?', aln.missing);
+        assert_eq!("Bunomys_andrewsi_HH718", aln.taxa[0]);
+        let path = Path::new("test_files/gene_2.nexus");
+        assert!(Alignment::from_file(path, &InputFmt::Nexus).is_ok());
+    }
+
+    #[test]
+    fn nexus_interleaved_test() {
+        let content = "#NEXUS\n[comment]\nbegin data;\ndimensions ntax=2 nchar=8;\n\
+            format datatype=dna missing=N gap=- interleave;\nmatrix\n\
+            'taxon a' ACGT\ntaxon_b AC-T\n\n'taxon a' ACGT\ntaxon_b ACNN\n;\nend;\n";
+        let aln = Alignment::parse(Path::new("a.nex"), content, &InputFmt::Nexus).unwrap();
+        assert_eq!(2, aln.ntax());
+        assert_eq!(8, aln.nchar());
+        assert_eq!('N', aln.missing);
+        assert_eq!(Some("AC-TACNN"), aln.get_sequence("taxon_b"));
+
+        let content = "#NEXUS\nbegin data;\ndimensions ntax=2 nchar=4;\nmatrix\n\
+            'it''s a' ACGT\n'''b''' AC-T\n;\nend;\n";
+        let aln = Alignment::parse(Path::new("a.nex"), content, &InputFmt::Nexus).unwrap();
+        assert_eq!(vec!["it's a", "'b'"], aln.taxa);
+    }
+
+    #[test]
+    fn nexus_error_test() {
+        let content = "#NEXUS\nbegin data;\ndimensions ntax=2 nchar=4;\nmatrix\n\
+            taxon_a ACGT\ntaxon_b ACG\n;\nend;\n";
+        let res = Alignment::parse(Path::new("a.nex"), content, &InputFmt::Nexus);
+        assert!(matches!(res, Err(MyteError::Parse { line: Some(6), .. })));
+    }
+
+    #[test]
+    fn fasta_test() {
+        let content = ">taxon_a\nACGT\nAC\n>taxon_b\nMKLV\nAC\n";
+        let aln = Alignment::parse(Path::new("a.fas"), content, &InputFmt::Fasta).unwrap();
+        assert_eq!(2, aln.ntax());
+        assert_eq!(6, aln.nchar());
+        assert_eq!(DataType::Protein, aln.datatype);
+
+        let content = ">taxon_a\nACGT\n>taxon_a\nACGT\n";
+        let res = Alignment::parse(Path::new("a.fas"), content, &InputFmt::Fasta);
+        assert!(matches!(res, Err(MyteError::Parse { line: Some(3), .. })));
+    }
+
+    #[test]
+    fn phylip_test() {
+        let content = "2 8 i\ntaxon_a ACGT\ntaxon_b AC-T\n\nACGT\nAC??\n";
+        let aln = Alignment::parse(Path::new("a.phy"), content, &InputFmt::Phylip).unwrap();
+        assert_eq!(Some("ACGTACGT"), aln.get_sequence("taxon_a"));
+        assert_eq!(8, aln.nchar());
+
+        let res = Alignment::parse(Path::new("a.phy"), "2\n", &InputFmt::Phylip);
+        assert!(matches!(res, Err(MyteError::Parse { line: Some(1), .. })));
+    }
+
+    #[test]
+    fn phylip_sequential_test() {
+        let content = "3 12\ntaxon_a ACGTAC\nGTACGT\ntaxon_b ACG TAC\nGTA CGT\n\
+            taxon_c ACGTACGTAC??\n";
+        let aln = Alignment::parse(Path::new("a.phy"), content, &InputFmt::Phylip).unwrap();
+        assert_eq!(vec!["taxon_a", "taxon_b", "taxon_c"], aln.taxa);
+        assert_eq!(Some("ACGTACGTACGT"), aln.get_sequence("taxon_b"));
+        let content = "2 8\ntaxon a   ACGT\nACGT\ntaxon_b123AC-T\nAC??\n";
+        let aln = Alignment::parse(Path::new("a.phy"), content, &InputFmt::PhylipStrict).unwrap();
+        assert_eq!(Some("AC-TAC??"), aln.get_sequence("taxon_b123"));
+
+        let content = "2 8\ntaxon_a ACGT\nACGT\ntaxon_b ACG\n";
+        let res = Alignment::parse(Path::new("a.phy"), content, &InputFmt::Phylip);
+        assert!(matches!(res, Err(MyteError::Parse { .. })));
+    }
+
+    #[test]
+    fn phylip_strict_test() {
+        let content = "2 8\ntaxon a   ACGT ACGT\ntaxon_b123AC-TAC??\n";
+        let aln = Alignment::parse(Path::new("a.phy"), content, &InputFmt::PhylipStrict).unwrap();
+        assert_eq!(vec!["taxon a", "taxon_b123"], aln.taxa);
+        assert_eq!(Some("AC-TAC??"), aln.get_sequence("taxon_b123"));
+    }
+
+    #[test]
+    fn auto_format_test() {
+        let content = "2 4\ntaxon_a ACGT\ntaxon_b AC-T\n";
+        let aln = Alignment::parse(Path::new("a.txt"), content, &InputFmt::Auto).unwrap();
+        assert_eq!(2, aln.ntax());
+        let res = Alignment::parse(Path::new("a.txt"), "locus\n", &InputFmt::Auto);
+        assert!(matches!(res, Err(MyteError::Parse { line: None, .. })));
+    }
+}
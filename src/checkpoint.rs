@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::error::{MyteError, Result};
+use crate::tree::OutputDir;
+
+const CHECKPOINT_NAME: &str = "myte_checkpoint.txt";
+
+// 64-bit FNV-1a. It is stable across Rust releases,
+// unlike the standard library hasher.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    SpeciesTree,
+    GeneTrees,
+    ConcordFactor,
+    MscTree,
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::SpeciesTree => "species-tree",
+            Stage::GeneTrees => "gene-trees",
+            Stage::ConcordFactor => "concordance-factor",
+            Stage::MscTree => "msc-tree",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "species-tree" => Some(Stage::SpeciesTree),
+            "gene-trees" => Some(Stage::GeneTrees),
+            "concordance-factor" => Some(Stage::ConcordFactor),
+            "msc-tree" => Some(Stage::MscTree),
+            _ => None,
+        }
+    }
+
+    fn outputs(&self, outputs: &OutputDir) -> Vec<PathBuf> {
+        match self {
+            Stage::SpeciesTree => vec![outputs.species_tree_file()],
+            Stage::GeneTrees => vec![outputs.gene_trees_file()],
+            Stage::ConcordFactor => vec![outputs.concord_tree_file()],
+            Stage::MscTree => vec![outputs.msc_tree_file()],
+        }
+    }
+
+    fn has_valid_outputs(&self, outputs: &OutputDir) -> bool {
+        self.outputs(outputs)
+            .iter()
+            .all(|path| is_valid_tree_file(path))
+    }
+}
+
+// Hash of the settings and inputs of a stage.
+// Values are separated, so ("ab", "c") and ("a", "bc") differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint(u64);
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+
+impl Fingerprint {
+    pub fn add<T: AsRef<[u8]>>(self, value: T) -> Self {
+        let hash = value
+            .as_ref()
+            .iter()
+            .chain(std::iter::once(&0xff))
+            .fold(self.0, |hash, &byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            });
+        Self(hash)
+    }
+
+    // Missing files are hashed as missing, so the stage runs again
+    // once they appear.
+    pub fn add_file(self, path: &Path) -> Self {
+        let fingerprint = self.add(path.to_string_lossy().as_bytes());
+        match fs::read(path) {
+            Ok(content) => fingerprint.add(content),
+            Err(_) => fingerprint.add("missing"),
+        }
+    }
+
+    pub fn add_files(self, paths: &[PathBuf]) -> Self {
+        paths
+            .iter()
+            .fold(self, |fingerprint, path| fingerprint.add_file(path))
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+// Keeps track of completed pipeline stages.
+// A manifest line is appended after every completed stage,
+// so an interrupted run can pick up where it stopped.
+// Each line records the fingerprint of the stage settings and inputs.
+// A stage only counts as completed when its fingerprint still matches.
+pub struct Checkpoint {
+    outputs: OutputDir,
+    manifest: PathBuf,
+    resume: bool,
+    dry_run: bool,
+    completed: HashMap<Stage, String>,
+}
+
+impl Checkpoint {
+    // Dry runs read the manifest, but never change it.
+    pub fn new(outputs: &OutputDir, resume: bool, dry_run: bool) -> Result<Self> {
+        let manifest = outputs.root().join(CHECKPOINT_NAME);
+        let mut completed = HashMap::new();
+        if resume {
+            completed = read_manifest(&manifest);
+        } else if manifest.is_file() && !dry_run {
+            fs::remove_file(&manifest).map_err(|err| MyteError::io(&manifest, err))?;
+        }
+
+        Ok(Self {
+            outputs: outputs.clone(),
+            manifest,
+            resume,
+            dry_run,
+            completed,
+        })
+    }
+
+    pub fn is_resuming(&self) -> bool {
+        self.resume
+    }
+
+    pub fn should_skip(&self, stage: Stage, fingerprint: &Fingerprint) -> bool {
+        if !self.resume {
+            return false;
+        }
+        match self.completed.get(&stage) {
+            Some(completed) if *completed == fingerprint.to_string() => {
+                stage.has_valid_outputs(&self.outputs)
+            }
+            Some(_) => {
+                log::info!(
+                    "Settings or inputs of stage {} changed since the checkpoint.\n",
+                    stage.name()
+                );
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn complete(&mut self, stage: Stage, fingerprint: &Fingerprint) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        if !stage.has_valid_outputs(&self.outputs) {
+            log::warn!(
+                "Stage {} finished without valid outputs. It will not be checkpointed.\n",
+                stage.name()
+            );
+            return Ok(());
+        }
+        self.completed.insert(stage, fingerprint.to_string());
+        self.write_manifest(stage, fingerprint)
+            .map_err(|err| MyteError::io(&self.manifest, err))
+    }
+
+    fn write_manifest(&self, stage: Stage, fingerprint: &Fingerprint) -> std::io::Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.manifest)?;
+        writeln!(
+            file,
+            "{}\t{}\t{}",
+            stage.name(),
+            fingerprint,
+            Local::now().format("%Y-%m-%d %H:%M:%S")
+        )?;
+        Ok(())
+    }
+}
+
+pub fn is_valid_tree_file(path: &Path) -> bool {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            let content = content.trim();
+            content.starts_with('(') && content.ends_with(';')
+        }
+        Err(_) => false,
+    }
+}
+
+// Later lines win, so a stage completed again
+// with new settings replaces the earlier one.
+fn read_manifest(manifest: &Path) -> HashMap<Stage, String> {
+    let file = match File::open(manifest) {
+        Ok(file) => file,
+        Err(_) => return HashMap::new(),
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let stage = fields
+                .next()
+                .and_then(|name| Stage::from_name(name.trim()))?;
+            let fingerprint = fields.next()?.trim().to_string();
+            Some((stage, fingerprint))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stage_name_test() {
+        let stages = [
+            Stage::SpeciesTree,
+            Stage::GeneTrees,
+            Stage::ConcordFactor,
+            Stage::MscTree,
+        ];
+        stages
+            .iter()
+            .for_each(|stage| assert_eq!(Some(*stage), Stage::from_name(stage.name())));
+    }
+
+    #[test]
+    fn fingerprint_test() {
+        let fingerprint = Fingerprint::default().add("-B 1000");
+        assert_eq!(fingerprint, Fingerprint::default().add("-B 1000"));
+        assert_ne!(fingerprint, Fingerprint::default().add("-B 2000"));
+        assert_ne!(
+            Fingerprint::default().add("ab").add("c"),
+            Fingerprint::default().add("a").add("bc")
+        );
+        assert_eq!(16, fingerprint.to_string().len());
+    }
+
+    #[test]
+    fn resume_stage_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        fs::write(outputs.msc_tree_file(), "(A,B,(C,D));").unwrap();
+        let fingerprint = Fingerprint::default().add("-t 4");
+        let mut checkpoint = Checkpoint::new(&outputs, false, false).unwrap();
+        checkpoint.complete(Stage::MscTree, &fingerprint).unwrap();
+        let checkpoint = Checkpoint::new(&outputs, true, false).unwrap();
+        assert!(checkpoint.should_skip(Stage::MscTree, &fingerprint));
+        assert!(!checkpoint.should_skip(Stage::MscTree, &fingerprint.add("-t 8")));
+        assert!(!checkpoint.should_skip(Stage::GeneTrees, &fingerprint));
+    }
+
+    #[test]
+    fn invalid_tree_file_test() {
+        let path = Path::new("test_files/gene_1.nexus");
+        assert!(!is_valid_tree_file(path));
+        assert!(!is_valid_tree_file(Path::new(
+            "test_files/missing.treefile"
+        )));
+    }
+}
@@ -1,67 +1,110 @@
-use std::io::Result;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
+use crate::checkpoint::{Checkpoint, Fingerprint, Stage};
+use crate::concat::{self, PartitionFmt};
+use crate::concordance::{self, DiscordanceCutoffs};
+use crate::config::{
+    ConcatConfig, ConcordanceConfig, Config, ExecutableConfig, FilterConfig, GeneTreeConfig,
+    StageConfig, TrimConfig, ValidationConfig,
+};
+use crate::convert;
 use crate::deps;
-use crate::tree::{self, InputFmt};
+use crate::error::{MyteError, Result};
+use crate::executor::{DryRunExecutor, Executor, SystemExecutor};
+use crate::filter::{self, LocusThresholds, TaxonFilter};
+use crate::input::{self, InputFiles, InputFmt, InputOptions, LociFilter};
+use crate::rooting;
+use crate::shrink::{self, ShrinkSettings};
+use crate::summary;
+use crate::tree::{self, Executables, FailurePolicy, GeneTreeSettings, OutputDir, TrimTool};
 use crate::utils;
+use crate::validation;
+use crate::writer::{Layout, OutputFmt};
+use ansi_term::Colour::Yellow;
 use clap::{crate_description, crate_name, App, AppSettings, Arg, ArgMatches};
 
 use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
-use log4rs::config::{Appender, Config, Root};
+use log4rs::config::{Appender, Config as LogConfig, Root};
 use log4rs::encode::pattern::PatternEncoder;
 
-fn get_args(version: &str) -> ArgMatches {
+const LOG_NAME: &str = "myte.log";
+const IQTREE_ENV: &str = "MYTE_IQTREE";
+const ASTRAL_ENV: &str = "MYTE_ASTRAL";
+const TRIMAL_ENV: &str = "MYTE_TRIMAL";
+const CLIPKIT_ENV: &str = "MYTE_CLIPKIT";
+
+fn get_args(version: &str) -> ArgMatches<'_> {
     App::new(crate_name!())
         .version(version)
         .about(crate_description!())
         .author("Heru Handika <hhandi1@lsu.edu>")
         .setting(AppSettings::SubcommandRequiredElseHelp)
-        .subcommand(App::new("check").about("Check dependencies"))
+        .subcommand(
+            App::new("check")
+                .about("Check dependencies")
+                .arg(iqtree_arg())
+                .arg(astral_arg())
+                .arg(trimal_arg())
+                .arg(clipkit_arg()),
+        )
         .subcommand(
             App::new("gene")
                 .about("Batch gene tree estimation using IQ-Tree")
+                .arg(dir_arg())
+                .arg(opts_g_arg())
+                .arg(output_arg())
+                .arg(dry_run_arg())
                 .arg(
-                    Arg::with_name("dir")
-                        .short("d")
-                        .long("dir")
-                        .help("Inputs folder path to locus alignment")
-                        .takes_value(true)
-                        .value_name("DIR"),
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .help("Skips loci finished with the same params and alignment")
+                        .takes_value(false),
                 )
-                .arg(
-                    Arg::with_name("opts-g")
-                        .long("opts-g")
-                        .help("Inputs params for IQ-TREE gene tree analyses")
-                        .takes_value(true)
-                        .value_name("STRING")
-                        .default_value("-T 1"),
-                )
-                .arg(
-                    Arg::with_name("input-fmt")
-                        .short("f")
-                        .long("input-fmt")
-                        .help("Sets input format")
-                        .required(true)
-                        .takes_value(true)
-                        .default_value("nexus")
-                        .possible_values(&["fasta", "phylip", "nexus"])
-                        .value_name("ALIGNMENT-FORMAT"),
-                ),
+                .arg(retries_arg())
+                .arg(fallback_opts_arg())
+                .arg(max_failures_arg())
+                .arg(collapse_below_arg())
+                .arg(shrink_arg())
+                .arg(shrink_alignments_arg())
+                .arg(no_shrink_alignments_arg())
+                .arg(outgroup_arg())
+                .arg(threads_arg())
+                .arg(iqtree_arg())
+                .arg(astral_arg())
+                .arg(trimal_arg())
+                .arg(clipkit_arg())
+                .arg(trim_arg())
+                .arg(opts_trimal_arg())
+                .arg(opts_clipkit_arg())
+                .arg(config_arg())
+                .arg(recursive_arg())
+                .arg(no_recursive_arg())
+                .arg(include_arg())
+                .arg(exclude_arg())
+                .arg(loci_list_arg())
+                .arg(skip_validation_arg())
+                .arg(no_skip_validation_arg())
+                .arg(exclude_invalid_arg())
+                .arg(no_exclude_invalid_arg())
+                .arg(min_taxa_arg())
+                .arg(min_length_arg())
+                .arg(min_pis_arg())
+                .arg(max_missing_arg())
+                .arg(min_occupancy_arg())
+                .arg(exclude_taxa_arg())
+                .arg(input_fmt_arg()),
         )
         .subcommand(
             App::new("auto")
                 .about(
                     "Estimate species tree, gene trees, gene and site concordance factors, and MSC tree",
                 )
-                .arg(
-                    Arg::with_name("dir")
-                        .short("d")
-                        .long("dir")
-                        .help("Inputs folder path to locus alignment")
-                        .takes_value(true)
-                        .value_name("STRING"),
-                )
+                .arg(dir_arg())
                 .arg(
                     Arg::with_name("opts-s")
                         .long("opts-s")
@@ -70,26 +113,164 @@ fn get_args(version: &str) -> ArgMatches {
                         .takes_value(true)
                         .value_name("PARAMS"),
                 )
+                .arg(opts_g_arg())
+                .arg(output_arg())
+                .arg(dry_run_arg())
+                .arg(retries_arg())
+                .arg(fallback_opts_arg())
+                .arg(max_failures_arg())
+                .arg(collapse_below_arg())
+                .arg(shrink_arg())
+                .arg(shrink_alignments_arg())
+                .arg(no_shrink_alignments_arg())
+                .arg(outgroup_arg())
+                .arg(threads_arg())
+                .arg(iqtree_arg())
+                .arg(astral_arg())
+                .arg(trimal_arg())
+                .arg(clipkit_arg())
+                .arg(trim_arg())
+                .arg(opts_trimal_arg())
+                .arg(opts_clipkit_arg())
+                .arg(config_arg())
+                .arg(recursive_arg())
+                .arg(no_recursive_arg())
+                .arg(include_arg())
+                .arg(exclude_arg())
+                .arg(loci_list_arg())
+                .arg(skip_validation_arg())
+                .arg(no_skip_validation_arg())
+                .arg(exclude_invalid_arg())
+                .arg(no_exclude_invalid_arg())
+                .arg(min_taxa_arg())
+                .arg(min_length_arg())
+                .arg(min_pis_arg())
+                .arg(max_missing_arg())
+                .arg(min_occupancy_arg())
+                .arg(exclude_taxa_arg())
+                .arg(input_fmt_arg())
                 .arg(
-                    Arg::with_name("opts-g")
-                        .long("opts-g")
-                        .help("Inputs params for IQ-TREE gene tree analyses")
+                    Arg::with_name("opts-a")
+                        .long("opts-a")
+                        .help("Inputs params for ASTRAL analyses")
                         .require_equals(true)
                         .takes_value(true)
-                        .value_name("STRING")
-                        .default_value("-T 1"),
+                        .value_name("PARAMS"),
+                )
+                .arg(
+                    Arg::with_name("discordant-support")
+                        .long("discordant-support")
+                        .help("Lists branches with at least this support as discordant")
+                        .takes_value(true)
+                        .default_value("95")
+                        .value_name("SUPPORT"),
+                )
+                .arg(
+                    Arg::with_name("discordant-gcf")
+                        .long("discordant-gcf")
+                        .help("Lists branches with a gCF below PERCENT as discordant")
+                        .takes_value(true)
+                        .default_value("50")
+                        .value_name("PERCENT"),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .help("Resumes a previous run, skipping completed stages and loci")
+                        .takes_value(false),
+                ),
+        )
+        .subcommand(
+            App::new("concat")
+                .about("Concatenates alignments into a supermatrix with a partition file")
+                .arg(dir_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("output-fmt")
+                        .long("output-fmt")
+                        .help("Sets the supermatrix format")
+                        .takes_value(true)
+                        .default_value("nexus")
+                        .possible_values(&["fasta", "phylip", "phylip-strict", "nexus"])
+                        .value_name("FORMAT"),
+                )
+                .arg(
+                    Arg::with_name("partition-fmt")
+                        .long("partition-fmt")
+                        .help("Sets the partition file format")
+                        .takes_value(true)
+                        .default_value("nexus")
+                        .possible_values(&["nexus", "raxml"])
+                        .value_name("FORMAT"),
                 )
+                .arg(config_arg())
+                .arg(recursive_arg())
+                .arg(no_recursive_arg())
+                .arg(include_arg())
+                .arg(exclude_arg())
+                .arg(loci_list_arg())
+                .arg(skip_validation_arg())
+                .arg(no_skip_validation_arg())
+                .arg(exclude_invalid_arg())
+                .arg(no_exclude_invalid_arg())
+                .arg(input_fmt_arg()),
+        )
+        .subcommand(
+            App::new("convert")
+                .about("Converts alignments between FASTA, PHYLIP, and NEXUS")
+                .arg(dir_arg())
+                .arg(output_arg())
                 .arg(
                     Arg::with_name("input-fmt")
                         .short("f")
-                        .long("input-fmt")
+                        .long("from")
                         .help("Sets input format")
-                        .required(true)
                         .takes_value(true)
-                        .default_value("nexus")
-                        .possible_values(&["fasta", "phylip", "nexus"])
+                        .default_value("auto")
+                        .possible_values(&["auto", "fasta", "phylip", "phylip-strict", "nexus"])
                         .value_name("ALIGNMENT-FORMAT"),
-                ),
+                )
+                .arg(
+                    Arg::with_name("output-fmt")
+                        .long("to")
+                        .help("Sets output format")
+                        .required(true)
+                        .takes_value(true)
+                        .possible_values(&["fasta", "phylip", "phylip-strict", "nexus"])
+                        .value_name("FORMAT"),
+                )
+                .arg(
+                    Arg::with_name("interleave")
+                        .long("interleave")
+                        .help("Writes interleaved PHYLIP and NEXUS alignments")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("line-width")
+                        .long("line-width")
+                        .help("Sets the number of sites per line for FASTA and interleaved output")
+                        .takes_value(true)
+                        .value_name("N"),
+                )
+                .arg(threads_arg())
+                .arg(recursive_arg())
+                .arg(include_arg())
+                .arg(exclude_arg())
+                .arg(loci_list_arg()),
+        )
+        .subcommand(
+            App::new("summary")
+                .about("Summarizes alignments into per-locus, overall, and taxon occupancy tables")
+                .arg(dir_arg())
+                .arg(output_arg())
+                .arg(threads_arg())
+                .arg(config_arg())
+                .arg(recursive_arg())
+                .arg(no_recursive_arg())
+                .arg(include_arg())
+                .arg(exclude_arg())
+                .arg(loci_list_arg())
+                .arg(input_fmt_arg()),
         )
         .subcommand(
             App::new("deps")
@@ -111,96 +292,1249 @@ fn get_args(version: &str) -> ArgMatches {
         .get_matches()
 }
 
-pub fn parse_cli(version: &str) {
+fn dir_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("dir")
+        .short("d")
+        .long("dir")
+        .help("Inputs folder paths to locus alignments")
+        .takes_value(true)
+        .multiple(true)
+        .value_name("DIR")
+}
+
+fn output_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .help("Sets the output directory")
+        .takes_value(true)
+        .default_value(".")
+        .value_name("DIR")
+}
+
+fn config_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("config")
+        .short("c")
+        .long("config")
+        .help("Inputs a config file (myte.toml). CLI flags override it")
+        .takes_value(true)
+        .value_name("FILE")
+}
+
+fn input_fmt_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("input-fmt")
+        .short("f")
+        .long("input-fmt")
+        .help("Sets input format")
+        .required(true)
+        .takes_value(true)
+        .default_value("nexus")
+        .possible_values(&["auto", "fasta", "phylip", "phylip-strict", "nexus"])
+        .value_name("ALIGNMENT-FORMAT")
+}
+
+fn recursive_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("recursive")
+        .long("recursive")
+        .help("Searches the input folders recursively")
+        .takes_value(false)
+}
+
+fn no_recursive_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("no-recursive")
+        .long("no-recursive")
+        .help("Searches only the top of the input folders, overriding the config")
+        .takes_value(false)
+        .overrides_with("recursive")
+}
+
+fn include_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("include")
+        .long("include")
+        .help("Keeps loci matching the glob patterns. Prefix with re: for regex")
+        .takes_value(true)
+        .multiple(true)
+        .value_name("PATTERN")
+}
+
+fn exclude_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("exclude")
+        .long("exclude")
+        .help("Leaves out loci matching the glob patterns. Prefix with re: for regex")
+        .takes_value(true)
+        .multiple(true)
+        .value_name("PATTERN")
+}
+
+fn loci_list_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("loci-list")
+        .long("loci-list")
+        .help("Inputs a file listing the loci to analyze, one per line")
+        .takes_value(true)
+        .value_name("FILE")
+}
+
+fn skip_validation_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("skip-validation")
+        .long("skip-validation")
+        .help("Skips checking the alignments before the analyses")
+        .takes_value(false)
+}
+
+fn no_skip_validation_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("no-skip-validation")
+        .long("no-skip-validation")
+        .help("Checks the alignments, overriding the config")
+        .takes_value(false)
+        .overrides_with("skip-validation")
+}
+
+fn exclude_invalid_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("exclude-invalid")
+        .long("exclude-invalid")
+        .help("Leaves out alignments that fail validation")
+        .takes_value(false)
+}
+
+fn no_exclude_invalid_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("no-exclude-invalid")
+        .long("no-exclude-invalid")
+        .help("Stops on alignments that fail validation, overriding the config")
+        .takes_value(false)
+        .overrides_with("exclude-invalid")
+}
+
+fn min_taxa_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("min-taxa")
+        .long("min-taxa")
+        .help("Leaves out loci with fewer than N taxa")
+        .takes_value(true)
+        .value_name("N")
+}
+
+fn min_length_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("min-length")
+        .long("min-length")
+        .help("Leaves out loci with fewer than N sites")
+        .takes_value(true)
+        .value_name("N")
+}
+
+fn min_pis_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("min-pis")
+        .long("min-pis")
+        .help("Leaves out loci with fewer than N parsimony informative sites")
+        .takes_value(true)
+        .value_name("N")
+}
+
+fn max_missing_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("max-missing")
+        .long("max-missing")
+        .help("Leaves out loci with a higher proportion of gaps and missing data")
+        .takes_value(true)
+        .value_name("RATIO")
+}
+
+fn min_occupancy_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("min-occupancy")
+        .long("min-occupancy")
+        .help("Drops taxa present in less than PERCENT of loci")
+        .takes_value(true)
+        .value_name("PERCENT")
+}
+
+fn exclude_taxa_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("exclude-taxa")
+        .long("exclude-taxa")
+        .help("Inputs a file listing taxa to drop, one per line")
+        .takes_value(true)
+        .value_name("FILE")
+}
+
+fn threads_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("threads")
+        .short("t")
+        .long("threads")
+        .help("Sets the number of threads")
+        .takes_value(true)
+        .value_name("N")
+}
+
+fn dry_run_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("dry-run")
+        .long("dry-run")
+        .help("Prints the commands without running them")
+        .takes_value(false)
+}
+
+fn opts_g_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("opts-g")
+        .long("opts-g")
+        .help("Inputs params for IQ-TREE gene tree analyses")
+        .require_equals(true)
+        .takes_value(true)
+        .value_name("STRING")
+        .default_value("-T 1")
+}
+
+fn retries_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("retries")
+        .long("retries")
+        .help("Re-runs IQ-TREE up to N times for failed loci")
+        .takes_value(true)
+        .value_name("N")
+}
+
+fn fallback_opts_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("fallback-opts")
+        .long("fallback-opts")
+        .require_equals(true)
+        .help("Inputs params for IQ-TREE gene tree retries")
+        .takes_value(true)
+        .value_name("STRING")
+}
+
+fn max_failures_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("max-failures")
+        .long("max-failures")
+        .help("Aborts gene tree estimation when more than N loci fail")
+        .takes_value(true)
+        .value_name("N")
+}
+
+fn collapse_below_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("collapse-below")
+        .long("collapse-below")
+        .help("Collapses gene tree branches with support below the threshold")
+        .takes_value(true)
+        .value_name("SUPPORT")
+}
+
+fn shrink_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("shrink")
+        .long("shrink")
+        .help("Removes gene tree tips that shrink the tree diameter by more than RATIO")
+        .takes_value(true)
+        .value_name("RATIO")
+}
+
+fn shrink_alignments_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("shrink-alignments")
+        .long("shrink-alignments")
+        .help("Writes the alignments without the shrunk tips. The analyses do not use them")
+        .takes_value(false)
+}
+
+fn no_shrink_alignments_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("no-shrink-alignments")
+        .long("no-shrink-alignments")
+        .help("Keeps the shrunk tips in the alignments, overriding the config")
+        .takes_value(false)
+        .overrides_with("shrink-alignments")
+}
+
+fn outgroup_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("outgroup")
+        .long("outgroup")
+        .help("Roots the final trees using the taxa, from the closest to the ingroup")
+        .takes_value(true)
+        .multiple(true)
+        .use_delimiter(true)
+        .value_name("TAXA")
+}
+
+fn trim_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("trim")
+        .long("trim")
+        .help("Trims the alignments before building trees")
+        .takes_value(true)
+        .possible_values(&["trimal", "clipkit"])
+        .value_name("TOOL")
+}
+
+fn opts_trimal_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("opts-trimal")
+        .long("opts-trimal")
+        .help("Inputs params for trimAl")
+        .require_equals(true)
+        .takes_value(true)
+        .value_name("PARAMS")
+}
+
+fn opts_clipkit_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("opts-clipkit")
+        .long("opts-clipkit")
+        .help("Inputs params for ClipKIT")
+        .require_equals(true)
+        .takes_value(true)
+        .value_name("PARAMS")
+}
+
+fn iqtree_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("iqtree")
+        .long("iqtree")
+        .help("Sets the IQ-TREE executable name or path")
+        .takes_value(true)
+        .value_name("PATH")
+}
+
+fn astral_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("astral")
+        .long("astral")
+        .help("Sets the ASTRAL executable name, path, or jar file")
+        .takes_value(true)
+        .value_name("PATH")
+}
+
+fn trimal_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("trimal")
+        .long("trimal")
+        .help("Sets the trimAl executable name or path")
+        .takes_value(true)
+        .value_name("PATH")
+}
+
+fn clipkit_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("clipkit")
+        .long("clipkit")
+        .help("Sets the ClipKIT executable name or path")
+        .takes_value(true)
+        .value_name("PATH")
+}
+
+pub fn parse_cli(version: &str) -> Result<()> {
     let args = get_args(version);
-    setup_logger().expect("Failed setting up a log file.");
+    // The config may set the output directory,
+    // so it is loaded before the logger.
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Failed loading the config file: {}", err);
+            return Err(err);
+        }
+    };
+    let log_dir = get_log_dir(&args, &config);
+    if let Err(err) = setup_logger(&log_dir) {
+        eprintln!("Failed setting up a log file: {}", err);
+        return Err(err);
+    }
     match args.subcommand() {
-        ("auto", Some(auto_matches)) => parse_auto_cli(auto_matches, &version),
-        ("gene", Some(gene_matches)) => parse_gene_cli(gene_matches, &version),
-        ("check", Some(_)) => display_app_info(&version),
+        ("auto", Some(auto_matches)) => parse_auto_cli(auto_matches, &config, version),
+        ("gene", Some(gene_matches)) => parse_gene_cli(gene_matches, &config, version),
+        ("concat", Some(concat_matches)) => parse_concat_cli(concat_matches, &config, version),
+        ("summary", Some(summary_matches)) => parse_summary_cli(summary_matches, &config, version),
+        ("convert", Some(convert_matches)) => parse_convert_cli(convert_matches, &config, version),
+        ("check", Some(check_matches)) => {
+            let settings = resolve_config(check_matches, &config)?;
+            display_app_info(version, &get_executables(&settings));
+            Ok(())
+        }
         ("deps", Some(deps_matches)) => parse_deps_cli(deps_matches),
         _ => unreachable!(),
     }
 }
 
-fn parse_deps_cli(args: &ArgMatches) {
+fn parse_deps_cli(args: &ArgMatches) -> Result<()> {
     match args.subcommand() {
         ("astral", Some(astral_matches)) => parse_astral_cli(astral_matches),
         _ => unreachable!(),
     }
 }
 
-fn parse_auto_cli(matches: &ArgMatches, version: &str) {
-    let path = get_path(matches);
+fn parse_auto_cli(matches: &ArgMatches, config: &Config, version: &str) -> Result<()> {
+    let dry_run = matches.is_present("dry-run");
+    let settings = resolve_config(matches, config)?;
+    let dirs = get_input_dirs(&settings, dry_run)?;
     let msg_len = 80;
-    let params_s = parse_params_species(matches);
-    let params_g = parse_params_gene(matches);
-    let input_fmt = parse_input_fmt(matches);
-    display_app_info(version);
+    let params_s = settings.species_tree.opts.clone();
+    let params_a = settings.astral.opts.clone();
+    let mut input_fmt = parse_input_fmt(&settings)?;
+    let output_fmt = parse_output_fmt(&settings)?;
+    let partition_fmt = parse_partition_fmt(&settings)?;
+    let outputs = get_outputs(&settings, dry_run)?;
+    let threads = set_thread_budget(settings.threads);
+    let exes = get_executables(&settings);
+    let executor = get_executor(dry_run);
+    let mut checkpoint = Checkpoint::new(&outputs, matches.is_present("resume"), dry_run)?;
+    let gene_settings = get_gene_tree_settings(&settings, checkpoint.is_resuming());
+    let params_g = &gene_settings.params;
+    display_app_info(version, &exes);
+    log_config(&settings);
+    print_dry_run_info(
+        dry_run,
+        &[
+            &params_s,
+            params_g,
+            &gene_settings.policy.fallback,
+            &params_a,
+        ],
+    );
+    let files = find_input_files(&dirs, &input_fmt, &settings, &outputs)?;
+    let alignments = get_valid_alignments(
+        files.alignments,
+        &input_fmt,
+        &settings,
+        &outputs,
+        dry_run,
+        msg_len,
+    )?;
+    let alignments = filter_taxa(
+        alignments, &input_fmt, &settings, &outputs, dry_run, msg_len,
+    )?;
+    let alignments = trim_alignments(
+        alignments,
+        &mut input_fmt,
+        &settings,
+        &outputs,
+        executor.as_ref(),
+        &exes,
+        msg_len,
+    )?;
+    // Thresholds check the alignments IQ-TREE gets.
+    let alignments = filter_alignments(
+        alignments, &input_fmt, &settings, &outputs, dry_run, msg_len,
+    )?;
+    let inputs = Fingerprint::default().add_files(&alignments);
     print_species_tree_header(msg_len);
-    log_input(&path, &params_s);
-    tree::build_species_tree(path, &params_s);
+    let fingerprint = get_fingerprint(Stage::SpeciesTree, &settings, inputs, &outputs);
+    if checkpoint.should_skip(Stage::SpeciesTree, &fingerprint) {
+        print_skip_stage();
+    } else {
+        let concat = concat::write_supermatrix(
+            &alignments,
+            &input_fmt,
+            &outputs,
+            &output_fmt,
+            &partition_fmt,
+            dry_run,
+        )?;
+        log_input(&concat.matrix.to_string_lossy(), &params_s);
+        tree::build_species_tree(
+            &concat.matrix,
+            &concat.partitions,
+            &params_s,
+            &outputs,
+            executor.as_ref(),
+            &exes,
+        )?;
+        checkpoint.complete(Stage::SpeciesTree, &fingerprint)?;
+    }
     print_gene_tree_header(msg_len);
-    log_input(&path, &params_g);
-    tree::build_gene_trees(path, &params_g, &input_fmt);
+    let mut failed_loci = 0;
+    let fingerprint = get_fingerprint(Stage::GeneTrees, &settings, inputs, &outputs);
+    if checkpoint.should_skip(Stage::GeneTrees, &fingerprint) {
+        print_skip_stage();
+    } else {
+        log_input(&dirs.join(", "), params_g);
+        failed_loci = tree::build_gene_trees(
+            &alignments,
+            &gene_settings,
+            &outputs,
+            executor.as_ref(),
+            &exes,
+        )?;
+        // Failed loci are re-estimated on resume.
+        if failed_loci == 0 {
+            checkpoint.complete(Stage::GeneTrees, &fingerprint)?;
+        }
+    }
     print_cf_tree_header(msg_len);
-    tree::estimate_concordance_factor(path);
+    let fingerprint = get_fingerprint(Stage::ConcordFactor, &settings, inputs, &outputs);
+    if checkpoint.should_skip(Stage::ConcordFactor, &fingerprint) {
+        print_skip_stage();
+    } else {
+        // The supermatrix is kept from the species tree stage.
+        let concat = concat::get_concat_files(&outputs, &output_fmt, &partition_fmt);
+        tree::estimate_concordance_factor(&concat, threads, &outputs, executor.as_ref(), &exes)?;
+        checkpoint.complete(Stage::ConcordFactor, &fingerprint)?;
+    }
+    // Parsing is cheap, so the tables follow the current cutoffs on resume.
+    concordance::summarize_concordance(&outputs, &get_discordance_cutoffs(&settings), dry_run)?;
+    // Shrinking is cheap, so it runs again with ASTRAL on resume.
+    let fingerprint = get_fingerprint(Stage::MscTree, &settings, inputs, &outputs);
+    let skip_msc = checkpoint.should_skip(Stage::MscTree, &fingerprint);
+    if let Some(shrink) = get_shrink_settings(&settings).filter(|_| !skip_msc) {
+        print_shrink_header(msg_len);
+        shrink::shrink_gene_trees(&shrink, &alignments, &input_fmt, &outputs, dry_run)?;
+    }
     print_msc_tree_header(msg_len);
-    tree::estimate_msc_tree(path);
+    if skip_msc {
+        print_skip_stage();
+    } else {
+        let gene_trees = get_msc_gene_trees(&settings, &outputs);
+        tree::estimate_msc_tree(&gene_trees, &params_a, &outputs, executor.as_ref(), &exes)?;
+        checkpoint.complete(Stage::MscTree, &fingerprint)?;
+    }
+    let mut trees = vec![
+        outputs.species_tree_file(),
+        outputs.concord_tree_file(),
+        outputs.msc_tree_file(),
+    ];
+    trees.extend(get_gene_tree_outputs(&settings, &outputs));
+    root_trees(&settings, &trees, &outputs, dry_run, msg_len)?;
     print_complete();
+    check_failed_loci(failed_loci, &outputs)
+}
+
+// Settings and inputs that change the outputs of a stage.
+// Later stages also hash the trees they read,
+// so they run again whenever an earlier stage does.
+fn get_fingerprint(
+    stage: Stage,
+    settings: &Config,
+    inputs: Fingerprint,
+    outputs: &OutputDir,
+) -> Fingerprint {
+    let genes = &settings.gene_trees;
+    match stage {
+        Stage::SpeciesTree => inputs
+            .add(format!("{:?}", settings.species_tree))
+            .add(format!("{:?}", settings.concat)),
+        Stage::GeneTrees => inputs
+            .add(format!("{:?}", genes.opts))
+            .add(format!("{:?}", genes.fallback_opts))
+            .add(format!("{:?}", genes.collapse_below)),
+        Stage::ConcordFactor => inputs
+            .add_file(&outputs.species_tree_file())
+            .add_file(&outputs.gene_trees_file()),
+        Stage::MscTree => Fingerprint::default()
+            .add_file(&outputs.gene_trees_file())
+            .add(format!("{:?}", settings.astral))
+            .add(format!("{:?}", genes.collapse_below))
+            .add(format!("{:?}", genes.shrink)),
+    }
 }
 
-fn parse_gene_cli(matches: &ArgMatches, version: &str) {
-    let path = get_path(matches);
+fn parse_gene_cli(matches: &ArgMatches, config: &Config, version: &str) -> Result<()> {
+    let dry_run = matches.is_present("dry-run");
+    let settings = resolve_config(matches, config)?;
+    let dirs = get_input_dirs(&settings, dry_run)?;
     let msg_len = 80;
-    let params = parse_params_gene(matches);
-    let input_fmt = parse_input_fmt(matches);
-    display_app_info(version);
+    let gene_settings = get_gene_tree_settings(&settings, matches.is_present("resume"));
+    let params = &gene_settings.params;
+    let mut input_fmt = parse_input_fmt(&settings)?;
+    let outputs = get_outputs(&settings, dry_run)?;
+    set_thread_budget(settings.threads);
+    let exes = get_executables(&settings);
+    let executor = get_executor(dry_run);
+    display_app_info(version, &exes);
+    log_config(&settings);
+    print_dry_run_info(dry_run, &[params, &gene_settings.policy.fallback]);
+    let files = find_input_files(&dirs, &input_fmt, &settings, &outputs)?;
+    let alignments = get_valid_alignments(
+        files.alignments,
+        &input_fmt,
+        &settings,
+        &outputs,
+        dry_run,
+        msg_len,
+    )?;
+    let alignments = filter_taxa(
+        alignments, &input_fmt, &settings, &outputs, dry_run, msg_len,
+    )?;
+    let alignments = trim_alignments(
+        alignments,
+        &mut input_fmt,
+        &settings,
+        &outputs,
+        executor.as_ref(),
+        &exes,
+        msg_len,
+    )?;
+    let alignments = filter_alignments(
+        alignments, &input_fmt, &settings, &outputs, dry_run, msg_len,
+    )?;
     print_gene_tree_header(msg_len);
-    tree::build_gene_trees(path, &params, &input_fmt);
+    log_input(&dirs.join(", "), params);
+    let failed_loci = tree::build_gene_trees(
+        &alignments,
+        &gene_settings,
+        &outputs,
+        executor.as_ref(),
+        &exes,
+    )?;
+    if let Some(shrink) = get_shrink_settings(&settings) {
+        print_shrink_header(msg_len);
+        shrink::shrink_gene_trees(&shrink, &alignments, &input_fmt, &outputs, dry_run)?;
+    }
+    let trees = get_gene_tree_outputs(&settings, &outputs);
+    root_trees(&settings, &trees, &outputs, dry_run, msg_len)?;
+    print_complete();
+    check_failed_loci(failed_loci, &outputs)
+}
+
+fn parse_concat_cli(matches: &ArgMatches, config: &Config, version: &str) -> Result<()> {
+    let settings = resolve_config(matches, config)?;
+    let dirs = get_input_dirs(&settings, false)?;
+    let msg_len = 80;
+    let input_fmt = parse_input_fmt(&settings)?;
+    let output_fmt = parse_output_fmt(&settings)?;
+    let partition_fmt = parse_partition_fmt(&settings)?;
+    let outputs = get_outputs(&settings, false)?;
+    print_app_info(version);
+    log_config(&settings);
+    let files = find_input_files(&dirs, &input_fmt, &settings, &outputs)?;
+    let alignments = get_valid_alignments(
+        files.alignments,
+        &input_fmt,
+        &settings,
+        &outputs,
+        false,
+        msg_len,
+    )?;
+    print_concat_header(msg_len);
+    concat::write_supermatrix(
+        &alignments,
+        &input_fmt,
+        &outputs,
+        &output_fmt,
+        &partition_fmt,
+        false,
+    )?;
+    print_complete();
+    Ok(())
+}
+
+fn parse_summary_cli(matches: &ArgMatches, config: &Config, version: &str) -> Result<()> {
+    let settings = resolve_config(matches, config)?;
+    let dirs = get_input_dirs(&settings, false)?;
+    let msg_len = 80;
+    let input_fmt = parse_input_fmt(&settings)?;
+    let outputs = get_outputs(&settings, false)?;
+    set_thread_budget(settings.threads);
+    print_app_info(version);
+    log_config(&settings);
+    let files = find_input_files(&dirs, &input_fmt, &settings, &outputs)?;
+    print_summary_header(msg_len);
+    summary::summarize_alignments(&files.alignments, &input_fmt, &outputs)?;
+    print_complete();
+    Ok(())
+}
+
+fn parse_convert_cli(matches: &ArgMatches, config: &Config, version: &str) -> Result<()> {
+    let settings = resolve_config(matches, config)?;
+    let dirs = get_input_dirs(&settings, false)?;
+    let msg_len = 80;
+    let input_fmt = parse_input_fmt(&settings)?;
+    let output_fmt = parse_output_fmt(&settings)?;
+    let layout = Layout {
+        interleaved: matches.is_present("interleave"),
+        line_width: get_number(matches, "line-width", None)?,
+    };
+    let outputs = get_outputs(&settings, false)?;
+    set_thread_budget(settings.threads);
+    print_app_info(version);
+    log_config(&settings);
+    let files = find_input_files(&dirs, &input_fmt, &settings, &outputs)?;
+    print_convert_header(msg_len);
+    convert::convert_alignments(
+        &files.alignments,
+        &input_fmt,
+        &output_fmt,
+        &layout,
+        &outputs,
+    )?;
     print_complete();
+    Ok(())
 }
 
-fn parse_astral_cli(matches: &ArgMatches) {
+fn parse_astral_cli(matches: &ArgMatches) -> Result<()> {
     let path = matches
         .value_of("jar")
-        .expect("CANNOT PARSE ASTRAL JAR PATH");
-    deps::fix_astral_dependency(path);
+        .ok_or_else(|| MyteError::Input(String::from("Missing the ASTRAL jar path")))?;
+    deps::fix_astral_dependency(path)
+}
+
+fn load_config(args: &ArgMatches) -> Result<Config> {
+    let path = match args.subcommand() {
+        (_, Some(matches)) => matches.value_of("config"),
+        _ => None,
+    };
+    match path {
+        Some(path) => Config::from_file(Path::new(path)),
+        None => Ok(Config::default()),
+    }
+}
+
+// Explicit CLI flags override the config file,
+// which overrides the CLI defaults.
+fn resolve_config(matches: &ArgMatches, config: &Config) -> Result<Config> {
+    Ok(Config {
+        dir: get_values(matches, "dir", &config.dir),
+        input_fmt: get_value(matches, "input-fmt", &config.input_fmt),
+        output: get_value(matches, "output", &config.output),
+        threads: get_number(matches, "threads", config.threads)?,
+        recursive: get_flag(matches, "recursive", config.recursive),
+        include: get_values(matches, "include", &config.include),
+        exclude: get_values(matches, "exclude", &config.exclude),
+        loci_list: get_value(matches, "loci-list", &config.loci_list),
+        outgroup: get_values(matches, "outgroup", &config.outgroup),
+        species_tree: StageConfig {
+            opts: get_value(matches, "opts-s", &config.species_tree.opts),
+        },
+        gene_trees: GeneTreeConfig {
+            opts: get_value(matches, "opts-g", &config.gene_trees.opts),
+            retries: get_number(matches, "retries", config.gene_trees.retries)?,
+            fallback_opts: get_value(matches, "fallback-opts", &config.gene_trees.fallback_opts),
+            max_failures: get_number(matches, "max-failures", config.gene_trees.max_failures)?,
+            collapse_below: get_float(
+                matches,
+                "collapse-below",
+                config.gene_trees.collapse_below,
+                100.0,
+            )?,
+            shrink: get_shrink_ratio(matches, config.gene_trees.shrink)?,
+            shrink_alignments: get_flag(
+                matches,
+                "shrink-alignments",
+                config.gene_trees.shrink_alignments,
+            ),
+        },
+        astral: StageConfig {
+            opts: get_value(matches, "opts-a", &config.astral.opts),
+        },
+        executables: ExecutableConfig {
+            iqtree: get_executable(matches, "iqtree", IQTREE_ENV, &config.executables.iqtree),
+            astral: get_executable(matches, "astral", ASTRAL_ENV, &config.executables.astral),
+            trimal: get_executable(matches, "trimal", TRIMAL_ENV, &config.executables.trimal),
+            clipkit: get_executable(matches, "clipkit", CLIPKIT_ENV, &config.executables.clipkit),
+        },
+        concat: ConcatConfig {
+            output_fmt: get_value(matches, "output-fmt", &config.concat.output_fmt),
+            partition_fmt: get_value(matches, "partition-fmt", &config.concat.partition_fmt),
+        },
+        trim: TrimConfig {
+            tool: get_value(matches, "trim", &config.trim.tool),
+            trimal_opts: get_value(matches, "opts-trimal", &config.trim.trimal_opts),
+            clipkit_opts: get_value(matches, "opts-clipkit", &config.trim.clipkit_opts),
+        },
+        validation: ValidationConfig {
+            skip: get_flag(matches, "skip-validation", config.validation.skip),
+            exclude_invalid: get_flag(
+                matches,
+                "exclude-invalid",
+                config.validation.exclude_invalid,
+            ),
+        },
+        filter: FilterConfig {
+            min_taxa: get_number(matches, "min-taxa", config.filter.min_taxa)?,
+            min_length: get_number(matches, "min-length", config.filter.min_length)?,
+            min_pis: get_number(matches, "min-pis", config.filter.min_pis)?,
+            max_missing: get_float(matches, "max-missing", config.filter.max_missing, 1.0)?,
+            min_occupancy: get_float(matches, "min-occupancy", config.filter.min_occupancy, 100.0)?,
+            exclude_taxa: get_value(matches, "exclude-taxa", &config.filter.exclude_taxa),
+        },
+        concordance: ConcordanceConfig {
+            discordant_support: get_float(
+                matches,
+                "discordant-support",
+                config.concordance.discordant_support,
+                100.0,
+            )?,
+            discordant_gcf: get_float(
+                matches,
+                "discordant-gcf",
+                config.concordance.discordant_gcf,
+                100.0,
+            )?,
+        },
+    })
+}
+
+// Environment variables sit between CLI flags and the config,
+// so a cluster module can set them for every run.
+fn get_executable(
+    matches: &ArgMatches,
+    name: &str,
+    env_var: &str,
+    config: &Option<String>,
+) -> Option<String> {
+    if matches.occurrences_of(name) > 0 {
+        return get_value(matches, name, config);
+    }
+    match env::var(env_var) {
+        Ok(exe) if !exe.trim().is_empty() => Some(String::from(exe.trim())),
+        _ => config.clone(),
+    }
+}
+
+fn get_value(matches: &ArgMatches, name: &str, config: &Option<String>) -> Option<String> {
+    if matches.occurrences_of(name) == 0 && config.is_some() {
+        return config.clone();
+    }
+    matches
+        .value_of(name)
+        .map(|value| String::from(value.trim()))
+}
+
+fn get_values(
+    matches: &ArgMatches,
+    name: &str,
+    config: &Option<Vec<String>>,
+) -> Option<Vec<String>> {
+    if matches.occurrences_of(name) == 0 && config.is_some() {
+        return config.clone();
+    }
+    matches
+        .values_of(name)
+        .map(|values| values.map(|value| String::from(value.trim())).collect())
+}
+
+// `--no-<flag>` turns off a flag set in the config.
+// The last of the two on the command line wins.
+fn get_flag(matches: &ArgMatches, name: &str, config: Option<bool>) -> Option<bool> {
+    if matches.is_present(name) {
+        return Some(true);
+    }
+    if matches.is_present(format!("no-{}", name)) {
+        return Some(false);
+    }
+    config
+}
+
+fn get_number(matches: &ArgMatches, name: &str, config: Option<usize>) -> Result<Option<usize>> {
+    let config = config.map(|value| value.to_string());
+    match get_value(matches, name, &config) {
+        Some(value) => value.parse::<usize>().map(Some).map_err(|_| {
+            MyteError::Input(format!(
+                "Invalid {} value: {}. It must be a non-negative integer",
+                name, value
+            ))
+        }),
+        None => Ok(None),
+    }
+}
+
+fn get_float(
+    matches: &ArgMatches,
+    name: &str,
+    config: Option<f64>,
+    max: f64,
+) -> Result<Option<f64>> {
+    let config = config.map(|value| value.to_string());
+    match get_value(matches, name, &config) {
+        Some(value) => match value.parse::<f64>() {
+            Ok(number) if (0.0..=max).contains(&number) => Ok(Some(number)),
+            _ => Err(MyteError::Input(format!(
+                "Invalid {} value: {}. It must be between 0 and {}",
+                name, value, max
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+// Ratios of 1 or less would remove tips that do not shrink the tree.
+fn get_shrink_ratio(matches: &ArgMatches, config: Option<f64>) -> Result<Option<f64>> {
+    match get_float(matches, "shrink", config, f64::MAX)? {
+        Some(ratio) if ratio <= 1.0 => Err(MyteError::Input(format!(
+            "Invalid shrink value: {}. It must be greater than 1",
+            ratio
+        ))),
+        ratio => Ok(ratio),
+    }
+}
+
+fn get_shrink_settings(settings: &Config) -> Option<ShrinkSettings> {
+    settings.gene_trees.shrink.map(|ratio| ShrinkSettings {
+        ratio,
+        collapse_below: settings.gene_trees.collapse_below,
+        prune_alignments: settings.gene_trees.shrink_alignments.unwrap_or(false),
+    })
+}
+
+fn get_discordance_cutoffs(settings: &Config) -> DiscordanceCutoffs {
+    let defaults = DiscordanceCutoffs::default();
+    DiscordanceCutoffs {
+        min_support: settings
+            .concordance
+            .discordant_support
+            .unwrap_or(defaults.min_support),
+        max_gcf: settings
+            .concordance
+            .discordant_gcf
+            .unwrap_or(defaults.max_gcf),
+    }
+}
+
+// Shrunk trees are also collapsed, so they take precedence.
+fn get_msc_gene_trees(settings: &Config, outputs: &OutputDir) -> PathBuf {
+    match (
+        settings.gene_trees.shrink,
+        settings.gene_trees.collapse_below,
+    ) {
+        (Some(_), _) => outputs.shrunk_gene_trees_file(),
+        (None, Some(_)) => outputs.collapsed_gene_trees_file(),
+        (None, None) => outputs.gene_trees_file(),
+    }
+}
+
+// Gene tree files written with the current settings, so files
+// left from earlier runs with other settings are not used.
+fn get_gene_tree_outputs(settings: &Config, outputs: &OutputDir) -> Vec<PathBuf> {
+    let mut trees = vec![outputs.gene_trees_file()];
+    if settings.gene_trees.collapse_below.is_some() {
+        trees.push(outputs.collapsed_gene_trees_file());
+    }
+    if settings.gene_trees.shrink.is_some() {
+        trees.push(outputs.shrunk_gene_trees_file());
+    }
+    trees
+}
+
+// Rooting is cheap, so it also runs on resume.
+fn root_trees(
+    settings: &Config,
+    trees: &[PathBuf],
+    outputs: &OutputDir,
+    dry_run: bool,
+    msg_len: usize,
+) -> Result<()> {
+    if let Some(outgroup) = &settings.outgroup {
+        print_rooting_header(msg_len);
+        rooting::root_trees(trees, outgroup, outputs, dry_run)?;
+    }
+    Ok(())
+}
+
+fn parse_input_fmt(settings: &Config) -> Result<InputFmt> {
+    match settings.input_fmt.as_deref() {
+        Some("auto") => Ok(InputFmt::Auto),
+        Some("fasta") => Ok(InputFmt::Fasta),
+        Some("nexus") | None => Ok(InputFmt::Nexus),
+        Some("phylip") => Ok(InputFmt::Phylip),
+        Some("phylip-strict") => Ok(InputFmt::PhylipStrict),
+        Some(fmt) => Err(MyteError::Input(format!(
+            "Invalid input format: {}. Use auto, fasta, nexus, phylip, or phylip-strict",
+            fmt
+        ))),
+    }
+}
+
+// Returns the alignments left for the analyses.
+fn get_valid_alignments(
+    alignments: Vec<PathBuf>,
+    input_fmt: &InputFmt,
+    settings: &Config,
+    outputs: &OutputDir,
+    dry_run: bool,
+    msg_len: usize,
+) -> Result<Vec<PathBuf>> {
+    if settings.validation.skip.unwrap_or(false) {
+        log::info!("Skipping alignment validation\n");
+        return Ok(alignments);
+    }
+    print_validation_header(msg_len);
+    let exclude_invalid = settings.validation.exclude_invalid.unwrap_or(false);
+    validation::validate_alignments(&alignments, input_fmt, outputs, exclude_invalid, dry_run)
+}
+
+// Returns the loci that pass the filters.
+fn filter_alignments(
+    alignments: Vec<PathBuf>,
+    input_fmt: &InputFmt,
+    settings: &Config,
+    outputs: &OutputDir,
+    dry_run: bool,
+    msg_len: usize,
+) -> Result<Vec<PathBuf>> {
+    let thresholds = get_thresholds(settings);
+    if thresholds.is_empty() {
+        return Ok(alignments);
+    }
+    print_filter_header(msg_len);
+    // Dry runs do not write the filtered alignments the thresholds check.
+    if dry_run && alignments.iter().any(|path| !path.is_file()) {
+        log::info!("Loci are filtered after the alignments are written\n");
+        return Ok(alignments);
+    }
+    filter::filter_loci(&alignments, input_fmt, &thresholds, outputs, dry_run)
+}
+
+// Returns the alignments with the taxa left,
+// or the input alignments when no taxa were dropped.
+fn filter_taxa(
+    alignments: Vec<PathBuf>,
+    input_fmt: &InputFmt,
+    settings: &Config,
+    outputs: &OutputDir,
+    dry_run: bool,
+    msg_len: usize,
+) -> Result<Vec<PathBuf>> {
+    let exclude_file = settings.filter.exclude_taxa.as_deref().map(Path::new);
+    let taxon_filter = TaxonFilter::new(settings.filter.min_occupancy, exclude_file)?;
+    if taxon_filter.is_empty() {
+        return Ok(alignments);
+    }
+    print_taxon_filter_header(msg_len);
+    let filtered = filter::filter_taxa(&alignments, input_fmt, &taxon_filter, outputs, dry_run)?;
+    Ok(filtered.unwrap_or(alignments))
+}
+
+fn get_thresholds(settings: &Config) -> LocusThresholds {
+    LocusThresholds {
+        min_taxa: settings.filter.min_taxa,
+        min_length: settings.filter.min_length,
+        min_pis: settings.filter.min_pis,
+        max_missing: settings.filter.max_missing,
+    }
 }
 
-fn parse_params_gene(matches: &ArgMatches) -> Option<String> {
-    let input = matches
-        .value_of("opts-g")
-        .expect("CANNOT PARSE PARAMS INPUT");
-    Some(String::from(input.trim()))
+// Returns the trimmed alignments, or the input alignments
+// when trimming is off. Trimmed alignments are FASTA.
+fn trim_alignments(
+    alignments: Vec<PathBuf>,
+    input_fmt: &mut InputFmt,
+    settings: &Config,
+    outputs: &OutputDir,
+    executor: &dyn Executor,
+    exes: &Executables,
+    msg_len: usize,
+) -> Result<Vec<PathBuf>> {
+    let tool = match parse_trim_tool(settings)? {
+        Some(tool) => tool,
+        None => return Ok(alignments),
+    };
+    print_trim_header(msg_len);
+    let params = get_trim_params(settings, &tool);
+    let trimmed = tree::trim_alignments(&alignments, &tool, &params, outputs, executor, exes)?;
+    *input_fmt = InputFmt::Fasta;
+    Ok(trimmed)
 }
 
-fn parse_params_species(matches: &ArgMatches) -> Option<String> {
-    let mut opts = None;
-    if matches.is_present("opts-s") {
-        let input = matches
-            .value_of("opts-s")
-            .expect("CANNOT PARSE PARAMS INPUT");
-        opts = Some(String::from(input.trim()));
+fn parse_trim_tool(settings: &Config) -> Result<Option<TrimTool>> {
+    match settings.trim.tool.as_deref() {
+        Some("trimal") => Ok(Some(TrimTool::Trimal)),
+        Some("clipkit") => Ok(Some(TrimTool::Clipkit)),
+        None => Ok(None),
+        Some(tool) => Err(MyteError::Input(format!(
+            "Invalid trimming tool: {}. Use trimal or clipkit",
+            tool
+        ))),
     }
+}
+
+fn get_trim_params(settings: &Config, tool: &TrimTool) -> Option<String> {
+    match tool {
+        TrimTool::Trimal => settings.trim.trimal_opts.clone(),
+        TrimTool::Clipkit => settings.trim.clipkit_opts.clone(),
+    }
+}
+
+fn parse_output_fmt(settings: &Config) -> Result<OutputFmt> {
+    match settings.concat.output_fmt.as_deref() {
+        Some("fasta") => Ok(OutputFmt::Fasta),
+        Some("nexus") | None => Ok(OutputFmt::Nexus),
+        Some("phylip") => Ok(OutputFmt::Phylip),
+        Some("phylip-strict") => Ok(OutputFmt::PhylipStrict),
+        Some(fmt) => Err(MyteError::Input(format!(
+            "Invalid output format: {}. Use fasta, nexus, phylip, or phylip-strict",
+            fmt
+        ))),
+    }
+}
+
+fn parse_partition_fmt(settings: &Config) -> Result<PartitionFmt> {
+    match settings.concat.partition_fmt.as_deref() {
+        Some("nexus") | None => Ok(PartitionFmt::Nexus),
+        Some("raxml") => Ok(PartitionFmt::Raxml),
+        Some(fmt) => Err(MyteError::Input(format!(
+            "Invalid partition format: {}. Use nexus or raxml",
+            fmt
+        ))),
+    }
+}
+
+fn get_gene_tree_settings(settings: &Config, resume: bool) -> GeneTreeSettings {
+    GeneTreeSettings {
+        params: settings.gene_trees.opts.clone(),
+        policy: FailurePolicy {
+            retries: settings.gene_trees.retries.unwrap_or(0),
+            fallback: settings.gene_trees.fallback_opts.clone(),
+            max_failures: settings.gene_trees.max_failures,
+        },
+        collapse_below: settings.gene_trees.collapse_below,
+        resume,
+    }
+}
+
+fn get_executables(settings: &Config) -> Executables {
+    let defaults = Executables::default();
+    Executables {
+        iqtree: settings
+            .executables
+            .iqtree
+            .clone()
+            .unwrap_or(defaults.iqtree),
+        astral: settings
+            .executables
+            .astral
+            .clone()
+            .unwrap_or(defaults.astral),
+        trimal: settings
+            .executables
+            .trimal
+            .clone()
+            .unwrap_or(defaults.trimal),
+        clipkit: settings
+            .executables
+            .clipkit
+            .clone()
+            .unwrap_or(defaults.clipkit),
+    }
+}
+
+// Limits the number of gene trees estimated at once.
+// Returns the threads for the concordance factor analyses.
+fn set_thread_budget(threads: Option<usize>) -> usize {
+    match threads {
+        Some(threads) if threads > 0 => {
+            if let Err(err) = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+            {
+                log::warn!("Failed setting the thread budget: {}", err);
+            }
+            threads
+        }
+        _ => num_cpus::get_physical(),
+    }
+}
+
+// Runs that finish with failed loci still exit with an error.
+fn check_failed_loci(failed: usize, outputs: &OutputDir) -> Result<()> {
+    if failed == 0 {
+        return Ok(());
+    }
+    Err(MyteError::LociFailed {
+        failed,
+        report: outputs.failed_loci_file(),
+    })
+}
+
+fn get_paths(settings: &Config) -> Result<&[String]> {
+    match settings.dir.as_deref() {
+        Some(dirs) if !dirs.is_empty() => Ok(dirs),
+        _ => Err(MyteError::Input(String::from(
+            "Missing the alignment directory. Specify it using -d or in the config file",
+        ))),
+    }
+}
+
+fn get_input_dirs(settings: &Config, dry_run: bool) -> Result<Vec<String>> {
+    let paths = get_paths(settings)?;
+    if dry_run {
+        return paths
+            .iter()
+            .map(|path| {
+                resolve_path(Path::new(path)).map(|path| path.to_string_lossy().to_string())
+            })
+            .collect();
+    }
+    Ok(paths.to_vec())
+}
 
-    opts
+fn find_input_files(
+    dirs: &[String],
+    input_fmt: &InputFmt,
+    settings: &Config,
+    outputs: &OutputDir,
+) -> Result<InputFiles> {
+    let filter = LociFilter::new(
+        settings.include.as_deref().unwrap_or_default(),
+        settings.exclude.as_deref().unwrap_or_default(),
+        settings.loci_list.as_deref().map(Path::new),
+    )?;
+    let opts = InputOptions {
+        recursive: settings.recursive.unwrap_or(false),
+        filter,
+        skip_dirs: outputs.output_dirs(),
+    };
+    input::find_alignments(dirs, input_fmt, &opts)
 }
 
-fn parse_input_fmt(matches: &ArgMatches) -> InputFmt {
-    let input_fmt = matches
-        .value_of("input-fmt")
-        .expect("CANNOT READ FORMAT INPUT");
-    match input_fmt {
-        "fasta" => InputFmt::Fasta,
-        "nexus" => InputFmt::Nexus,
-        "phylip" => InputFmt::Phylip,
-        _ => unreachable!("Specify input format"),
+fn get_outputs(settings: &Config, dry_run: bool) -> Result<OutputDir> {
+    let output = Path::new(settings.output.as_deref().unwrap_or("."));
+    if dry_run {
+        return Ok(OutputDir::new(resolve_path(output)?));
     }
+    Ok(OutputDir::new(output))
+}
+
+// Dry runs print absolute paths, so the command plan
+// is unambiguous when reviewed elsewhere.
+fn resolve_path(path: &Path) -> Result<PathBuf> {
+    match path.canonicalize() {
+        Ok(path) => Ok(path),
+        Err(_) => {
+            let cwd = std::env::current_dir().map_err(|err| MyteError::io(".", err))?;
+            Ok(cwd.join(path))
+        }
+    }
+}
+
+fn get_executor(dry_run: bool) -> Box<dyn Executor> {
+    if dry_run {
+        Box::new(DryRunExecutor::new())
+    } else {
+        Box::new(SystemExecutor)
+    }
+}
+
+// Only analysis subcommands accept an output directory.
+// The rest write the log in the current directory.
+fn get_log_dir(args: &ArgMatches, config: &Config) -> String {
+    let output = match args.subcommand() {
+        (_, Some(matches)) => get_value(matches, "output", &config.output),
+        _ => None,
+    };
+    output.unwrap_or_else(|| String::from("."))
 }
 
-fn get_path<'a>(matches: &'a ArgMatches) -> &'a str {
-    matches.value_of("dir").expect("CANNOT GET DIRECTORY PATH")
+fn print_validation_header(len: usize) {
+    let text = "ALIGNMENT VALIDATION";
+    utils::print_divider(text, len);
+}
+
+fn print_taxon_filter_header(len: usize) {
+    let text = "TAXON FILTERING";
+    utils::print_divider(text, len);
+}
+
+fn print_filter_header(len: usize) {
+    let text = "LOCUS FILTERING";
+    utils::print_divider(text, len);
+}
+
+fn print_convert_header(len: usize) {
+    let text = "FORMAT CONVERSION";
+    utils::print_divider(text, len);
+}
+
+fn print_summary_header(len: usize) {
+    let text = "ALIGNMENT SUMMARY";
+    utils::print_divider(text, len);
+}
+
+fn print_concat_header(len: usize) {
+    let text = "SUPERMATRIX";
+    utils::print_divider(text, len);
+}
+
+fn print_trim_header(len: usize) {
+    let text = "ALIGNMENT TRIMMING";
+    utils::print_divider(text, len);
+}
+
+fn print_shrink_header(len: usize) {
+    let text = "GENE TREE SHRINKING";
+    utils::print_divider(text, len);
+}
+
+fn print_rooting_header(len: usize) {
+    let text = "TREE ROOTING";
+    utils::print_divider(text, len);
 }
 
 fn print_species_tree_header(len: usize) {
@@ -223,12 +1557,41 @@ fn print_msc_tree_header(len: usize) {
     utils::print_divider(text, len);
 }
 
-fn display_app_info(version: &str) {
+fn display_app_info(version: &str, exes: &Executables) {
+    print_app_info(version);
+    deps::check_dependencies(exes);
+}
+
+fn print_app_info(version: &str) {
     log::info!("{} v{}", crate_name!(), version);
     log::info!("{}", crate_description!());
     log::info!("Developed by Heru Handika\n");
     utils::get_system_info();
-    deps::check_dependencies();
+}
+
+fn print_dry_run_info(dry_run: bool, params: &[&Option<String>]) {
+    if !dry_run {
+        return;
+    }
+    log::info!("{}", Yellow.paint("Dry Run"));
+    log::info!("No analyses will run. Printing the command plan.");
+    params.iter().for_each(|param| {
+        if let Some(param) = param {
+            let tokens: Vec<&str> = param.split_whitespace().collect();
+            log::info!("{:18}: {:?}", "Parsed params", tokens);
+        }
+    });
+    println!();
+}
+
+// The resolved config is valid myte.toml,
+// so a run can be repeated using --config.
+fn log_config(settings: &Config) {
+    log::info!("{:18}:\n{}", "Resolved config", settings.to_toml());
+}
+
+fn print_skip_stage() {
+    log::info!("Found a completed checkpoint. Skipping this stage!\n");
 }
 
 fn print_complete() {
@@ -244,20 +1607,22 @@ fn log_input(path: &str, params: &Option<String>) {
     }
 }
 
-fn setup_logger() -> Result<()> {
-    let log_dir = std::env::current_dir()?;
-    let target = log_dir.join("myte.log");
+fn setup_logger(log_dir: &str) -> Result<()> {
+    let log_dir = Path::new(log_dir);
+    fs::create_dir_all(log_dir).map_err(|err| MyteError::io(log_dir, err))?;
+    let target = log_dir.join(LOG_NAME);
     let tofile = FileAppender::builder()
         .encoder(Box::new(PatternEncoder::new(
             "{d(%Y-%m-%d %H:%M:%S %Z)} - {l} - {m}\n",
         )))
-        .build(target)?;
+        .build(&target)
+        .map_err(|err| MyteError::io(&target, err))?;
 
     let stdout = ConsoleAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{m}\n")))
         .build();
 
-    let config = Config::builder()
+    let config = LogConfig::builder()
         .appender(Appender::builder().build("stdout", Box::new(stdout)))
         .appender(Appender::builder().build("logfile", Box::new(tofile)))
         .build(
@@ -266,9 +1631,16 @@ fn setup_logger() -> Result<()> {
                 .appender("logfile")
                 .build(LevelFilter::Info),
         )
-        .unwrap();
+        .map_err(|err| logger_error(&target, err))?;
 
-    log4rs::init_config(config).unwrap();
+    log4rs::init_config(config).map_err(|err| logger_error(&target, err))?;
 
     Ok(())
 }
+
+fn logger_error<E: ToString>(target: &Path, err: E) -> MyteError {
+    MyteError::io(
+        target,
+        io::Error::new(io::ErrorKind::Other, err.to_string()),
+    )
+}
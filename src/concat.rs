@@ -0,0 +1,285 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::alignment::{Alignment, DataType};
+use crate::error::{MyteError, Result};
+use crate::input::InputFmt;
+use crate::tree::{self, OutputDir};
+use crate::writer::{self, OutputFmt};
+
+const CONCAT_PREFIX: &str = "concat";
+const CONCAT_MISSING: char = '?';
+const CONCAT_GAP: char = '-';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionFmt {
+    Nexus,
+    Raxml,
+}
+
+impl PartitionFmt {
+    fn extension(&self) -> &'static str {
+        match self {
+            PartitionFmt::Nexus => "nex",
+            PartitionFmt::Raxml => "txt",
+        }
+    }
+}
+
+// Sites of a locus in the supermatrix. Positions start from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    pub locus: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Supermatrix {
+    pub alignment: Alignment,
+    pub partitions: Vec<Partition>,
+}
+
+// Files written for the supermatrix
+pub struct ConcatFiles {
+    pub matrix: PathBuf,
+    pub partitions: PathBuf,
+}
+
+// Concatenates alignments in the given order. Taxa missing
+// from a locus are filled with gaps. Taxa are sorted by name.
+pub fn concat_alignments(paths: &[PathBuf], input_fmt: &InputFmt) -> Result<Supermatrix> {
+    if paths.is_empty() {
+        return Err(MyteError::Input(String::from(
+            "Found no alignments to concatenate",
+        )));
+    }
+    let alignments = paths
+        .par_iter()
+        .map(|path| Alignment::from_file(path, input_fmt))
+        .collect::<Result<Vec<Alignment>>>()?;
+    let datatype = get_datatype(&alignments)?;
+    let taxa: Vec<String> = alignments
+        .iter()
+        .flat_map(|aln| aln.taxa.iter().cloned())
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect();
+    let mut sequences = vec![String::new(); taxa.len()];
+    let mut partitions = Vec::with_capacity(alignments.len());
+    let mut start = 1;
+    alignments.iter().for_each(|aln| {
+        let nchar = aln.nchar();
+        taxa.iter()
+            .zip(sequences.iter_mut())
+            .for_each(|(taxon, matrix_seq)| match aln.get_sequence(taxon) {
+                Some(seq) => matrix_seq.extend(seq.chars().map(|c| normalize_char(aln, c))),
+                None => matrix_seq.extend(std::iter::repeat(CONCAT_GAP).take(nchar)),
+            });
+        partitions.push(Partition {
+            locus: tree::get_locus_name(&aln.path).to_string(),
+            start,
+            end: start + nchar - 1,
+        });
+        start += nchar;
+    });
+    Ok(Supermatrix {
+        alignment: Alignment {
+            path: PathBuf::from(CONCAT_PREFIX),
+            taxa,
+            sequences,
+            datatype,
+            missing: CONCAT_MISSING,
+            gap: CONCAT_GAP,
+        },
+        partitions,
+    })
+}
+
+// Builds and writes the supermatrix and its partitions.
+// Dry runs only return the paths.
+pub fn write_supermatrix(
+    paths: &[PathBuf],
+    input_fmt: &InputFmt,
+    outputs: &OutputDir,
+    output_fmt: &OutputFmt,
+    partition_fmt: &PartitionFmt,
+    dry_run: bool,
+) -> Result<ConcatFiles> {
+    let dir = outputs.concat_dir();
+    let files = get_concat_files(outputs, output_fmt, partition_fmt);
+    if dry_run {
+        return Ok(files);
+    }
+    let matrix = concat_alignments(paths, input_fmt)?;
+    fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    writer::write_alignment(&matrix.alignment, &files.matrix, output_fmt)?;
+    write_partitions(&matrix, &files.partitions, partition_fmt)
+        .map_err(|err| MyteError::io(&files.partitions, err))?;
+    print_concat_info(&matrix, &files);
+    Ok(files)
+}
+
+// Paths of the supermatrix and its partitions in the output directory
+pub fn get_concat_files(
+    outputs: &OutputDir,
+    output_fmt: &OutputFmt,
+    partition_fmt: &PartitionFmt,
+) -> ConcatFiles {
+    let dir = outputs.concat_dir();
+    ConcatFiles {
+        matrix: writer::get_output_path(&dir, CONCAT_PREFIX, output_fmt),
+        partitions: dir.join(format!(
+            "{}_partitions.{}",
+            CONCAT_PREFIX,
+            partition_fmt.extension()
+        )),
+    }
+}
+
+fn get_datatype(alignments: &[Alignment]) -> Result<DataType> {
+    let datatype = match alignments[0].datatype {
+        DataType::Rna => DataType::Dna,
+        datatype => datatype,
+    };
+    let mixed = alignments.iter().find(|aln| match aln.datatype {
+        DataType::Dna | DataType::Rna => datatype != DataType::Dna,
+        other => other != datatype,
+    });
+    match mixed {
+        Some(aln) => Err(MyteError::Input(format!(
+            "Cannot concatenate {:?} data in {} with {:?} data",
+            aln.datatype,
+            aln.path.display(),
+            datatype
+        ))),
+        None => Ok(datatype),
+    }
+}
+
+// Loci may use their own symbols for missing data and gaps.
+fn normalize_char(aln: &Alignment, c: char) -> char {
+    if c == aln.gap {
+        CONCAT_GAP
+    } else if c == aln.missing {
+        CONCAT_MISSING
+    } else {
+        c
+    }
+}
+
+fn write_partitions(
+    matrix: &Supermatrix,
+    path: &Path,
+    partition_fmt: &PartitionFmt,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match partition_fmt {
+        PartitionFmt::Nexus => {
+            writeln!(writer, "#nexus")?;
+            writeln!(writer, "begin sets;")?;
+            matrix.partitions.iter().try_for_each(|part| {
+                writeln!(
+                    writer,
+                    "\tcharset {} = {}-{};",
+                    part.locus, part.start, part.end
+                )
+            })?;
+            writeln!(writer, "end;")?;
+        }
+        PartitionFmt::Raxml => {
+            let model = get_raxml_model(&matrix.alignment.datatype);
+            matrix.partitions.iter().try_for_each(|part| {
+                writeln!(
+                    writer,
+                    "{}, {} = {}-{}",
+                    model, part.locus, part.start, part.end
+                )
+            })?;
+        }
+    }
+    writer.flush()
+}
+
+fn get_raxml_model(datatype: &DataType) -> &'static str {
+    match datatype {
+        DataType::Dna | DataType::Rna => "DNA",
+        DataType::Protein => "WAG",
+        DataType::Standard => "MULTI",
+    }
+}
+
+fn print_concat_info(matrix: &Supermatrix, files: &ConcatFiles) {
+    log::info!("{:18}: {}", "Loci", matrix.partitions.len());
+    log::info!("{:18}: {}", "Taxa", matrix.alignment.ntax());
+    log::info!("{:18}: {}", "Sites", matrix.alignment.nchar());
+    log::info!("{:18}: {}", "Supermatrix", files.matrix.display());
+    log::info!("{:18}: {}\n", "Partitions", files.partitions.display());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_test_alignments(dir: &Path) -> Vec<PathBuf> {
+        let gene_1 = dir.join("gene_1.fas");
+        fs::write(&gene_1, ">b\nACGT\n>a\nAC-T\n").unwrap();
+        let gene_2 = dir.join("gene_2.fas");
+        fs::write(&gene_2, ">c\nGG\n>a\nG?\n").unwrap();
+        vec![gene_1, gene_2]
+    }
+
+    #[test]
+    fn concat_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = write_test_alignments(dir.path());
+        let matrix = concat_alignments(&paths, &InputFmt::Fasta).unwrap();
+        let aln = &matrix.alignment;
+        assert_eq!(vec!["a", "b", "c"], aln.taxa);
+        assert_eq!(Some("AC-TG?"), aln.get_sequence("a"));
+        assert_eq!(Some("ACGT--"), aln.get_sequence("b"));
+        assert_eq!(Some("----GG"), aln.get_sequence("c"));
+        assert_eq!(
+            Partition {
+                locus: String::from("gene_2"),
+                start: 5,
+                end: 6
+            },
+            matrix.partitions[1]
+        );
+    }
+
+    #[test]
+    fn write_supermatrix_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = write_test_alignments(dir.path());
+        let outputs = OutputDir::new(dir.path().join("results"));
+        let files = write_supermatrix(
+            &paths,
+            &InputFmt::Fasta,
+            &outputs,
+            &OutputFmt::Phylip,
+            &PartitionFmt::Raxml,
+            false,
+        )
+        .unwrap();
+        let aln = Alignment::from_file(&files.matrix, &InputFmt::Phylip).unwrap();
+        assert_eq!(6, aln.nchar());
+        let partitions = fs::read_to_string(&files.partitions).unwrap();
+        assert_eq!("DNA, gene_1 = 1-4\nDNA, gene_2 = 5-6\n", partitions);
+    }
+
+    #[test]
+    fn mixed_datatype_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut paths = write_test_alignments(dir.path());
+        let protein = dir.path().join("gene_3.fas");
+        fs::write(&protein, ">a\nMKLV\n").unwrap();
+        paths.push(protein);
+        let res = concat_alignments(&paths, &InputFmt::Fasta);
+        assert!(matches!(res, Err(MyteError::Input(_))));
+    }
+}
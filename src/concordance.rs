@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use ansi_term::Colour::Yellow;
+
+use crate::error::{MyteError, Result};
+use crate::newick::{self, Tree};
+use crate::summary::csv_field;
+use crate::tree::OutputDir;
+use crate::utils;
+
+// Default cutoffs for listing a branch as discordant
+const DISCORDANT_MIN_SUPPORT: f64 = 95.0;
+const DISCORDANT_MAX_GCF: f64 = 50.0;
+
+// Columns of the concordance table and the discordant branch report
+const BRANCH_HEADER: &str = "branch,gCF,gDF1,gDF2,gDFP,gN,sCF,sDF1,sDF2,sN,support,length,clade";
+
+// Columns read from the IQ-TREE statistics
+const STAT_COLUMNS: [&str; 10] = [
+    "ID", "gCF", "gDF1", "gDF2", "gDFP", "gN", "sCF", "sDF1", "sDF2", "sN",
+];
+
+// Concordance factors are percentages. NA values are NaN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BranchConcordance {
+    pub id: usize,
+    pub gcf: f64,
+    pub gdf1: f64,
+    pub gdf2: f64,
+    pub gdfp: f64,
+    pub gn: usize,
+    pub scf: f64,
+    pub sdf1: f64,
+    pub sdf2: f64,
+    pub sn: usize,
+    pub support: Option<f64>,
+    pub length: Option<f64>,
+    // Taxa below the branch in the IQ-TREE tree
+    pub clade: Vec<String>,
+}
+
+// Branches with at least the support and a gCF below
+// the maximum are listed as discordant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiscordanceCutoffs {
+    pub min_support: f64,
+    pub max_gcf: f64,
+}
+
+impl Default for DiscordanceCutoffs {
+    fn default() -> Self {
+        Self {
+            min_support: DISCORDANT_MIN_SUPPORT,
+            max_gcf: DISCORDANT_MAX_GCF,
+        }
+    }
+}
+
+impl BranchConcordance {
+    pub fn is_discordant(&self, cutoffs: &DiscordanceCutoffs) -> bool {
+        matches!(self.support, Some(support) if support >= cutoffs.min_support)
+            && self.gcf < cutoffs.max_gcf
+    }
+}
+
+// Parses the concordance factor outputs in the concordance directory
+// and writes them as CSV. Returns the branches ordered by ID.
+pub fn summarize_concordance(
+    outputs: &OutputDir,
+    cutoffs: &DiscordanceCutoffs,
+    dry_run: bool,
+) -> Result<Vec<BranchConcordance>> {
+    let table = outputs.concordance_table_file();
+    let report = outputs.discordant_branches_file();
+    if dry_run {
+        log::info!("{:18}: {}\n", "Concordance table", table.display());
+        return Ok(Vec::new());
+    }
+    let mut branches = read_stat_file(&outputs.concord_stat_file())?;
+    add_tree_labels(
+        &mut branches,
+        &outputs.concord_branch_file(),
+        &outputs.concord_tree_file(),
+    )?;
+    write_branches(&table, &branches).map_err(|err| MyteError::io(&table, err))?;
+    let discordant: Vec<BranchConcordance> = branches
+        .iter()
+        .filter(|branch| branch.is_discordant(cutoffs))
+        .cloned()
+        .collect();
+    write_discordant_report(&report, &discordant)?;
+    print_concordance_info(&branches, &discordant, cutoffs, &table, &report);
+    Ok(branches)
+}
+
+// Columns are matched by name, so their order does not matter.
+// Label and Length are optional.
+fn read_stat_file(path: &Path) -> Result<Vec<BranchConcordance>> {
+    let content = fs::read_to_string(path).map_err(|err| MyteError::io(path, err))?;
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    let header: HashMap<&str, usize> = match lines.next() {
+        Some((_, line)) => line
+            .split('\t')
+            .enumerate()
+            .map(|(i, column)| (column.trim(), i))
+            .collect(),
+        None => return Err(parse_error(path, None, "Found no header")),
+    };
+    if let Some(column) = STAT_COLUMNS.iter().find(|c| !header.contains_key(*c)) {
+        let message = format!("Missing the {} column", column);
+        return Err(parse_error(path, None, &message));
+    }
+    let mut branches = lines
+        .map(|(i, line)| parse_stat_row(path, i + 1, line, &header))
+        .collect::<Result<Vec<BranchConcordance>>>()?;
+    branches.sort_by_key(|branch| branch.id);
+    Ok(branches)
+}
+
+fn parse_stat_row(
+    path: &Path,
+    line_num: usize,
+    line: &str,
+    header: &HashMap<&str, usize>,
+) -> Result<BranchConcordance> {
+    let fields: Vec<&str> = line.split('\t').map(|field| field.trim()).collect();
+    let field = |column: &str| {
+        header
+            .get(column)
+            .and_then(|&i| fields.get(i))
+            .copied()
+            .unwrap_or("")
+    };
+    let value = |column: &str| -> Result<f64> {
+        match field(column) {
+            "" | "NA" => Ok(f64::NAN),
+            value => value.parse::<f64>().map_err(|_| {
+                let message = format!("Invalid {} value: {}", column, value);
+                parse_error(path, Some(line_num), &message)
+            }),
+        }
+    };
+    let count = |column: &str| -> Result<usize> {
+        match value(column)? {
+            count if count >= 0.0 => Ok(count.round() as usize),
+            _ => Ok(0),
+        }
+    };
+    let id = field("ID").parse::<usize>().map_err(|_| {
+        let message = format!("Invalid branch ID: {}", field("ID"));
+        parse_error(path, Some(line_num), &message)
+    })?;
+    Ok(BranchConcordance {
+        id,
+        gcf: value("gCF")?,
+        gdf1: value("gDF1")?,
+        gdf2: value("gDF2")?,
+        gdfp: value("gDFP")?,
+        gn: count("gN")?,
+        scf: value("sCF")?,
+        sdf1: value("sDF1")?,
+        sdf2: value("sDF2")?,
+        sn: count("sN")?,
+        support: newick::parse_support(field("Label")),
+        length: field("Length").parse::<f64>().ok(),
+        clade: Vec::new(),
+    })
+}
+
+// The branch tree and the concordance tree are the same tree
+// with different labels. Walking both in the same order matches
+// each branch ID to its clade and its support/gCF/sCF label.
+fn add_tree_labels(
+    branches: &mut [BranchConcordance],
+    branch_file: &Path,
+    tree_file: &Path,
+) -> Result<()> {
+    if !branch_file.is_file() || !tree_file.is_file() {
+        return Ok(());
+    }
+    let ids = Tree::from_file(branch_file)?.remove(0);
+    let labels = Tree::from_file(tree_file)?.remove(0);
+    let (id_nodes, label_nodes) = (ids.preorder(), labels.preorder());
+    if ids.taxa() != labels.taxa() || id_nodes.len() != label_nodes.len() {
+        log::warn!(
+            "{}: {} does not match {}. Clades are not reported.",
+            Yellow.paint("WARNING"),
+            branch_file.display(),
+            tree_file.display()
+        );
+        return Ok(());
+    }
+    let mut tree_labels = HashMap::new();
+    id_nodes
+        .iter()
+        .zip(label_nodes.iter())
+        .filter(|(&id, _)| !ids.node(id).is_leaf())
+        .for_each(|(&id_node, &label_node)| {
+            let branch_id = ids.node(id_node).label.as_deref();
+            if let Some(branch_id) = branch_id.and_then(|id| id.parse::<usize>().ok()) {
+                tree_labels.insert(branch_id, (id_node, labels.node(label_node).supports()));
+            }
+        });
+    branches.iter_mut().for_each(|branch| {
+        if let Some((node, supports)) = tree_labels.get(&branch.id) {
+            branch.clade = ids
+                .taxa_below(*node)
+                .into_iter()
+                .map(String::from)
+                .collect();
+            // Support comes before gCF/sCF when the tree has one
+            if branch.support.is_none() && supports.len() >= 3 {
+                branch.support = Some(supports[supports.len() - 3]);
+            }
+        }
+    });
+    Ok(())
+}
+
+fn write_branches(path: &Path, branches: &[BranchConcordance]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", BRANCH_HEADER)?;
+    branches
+        .iter()
+        .try_for_each(|branch| writeln!(writer, "{}", format_branch(branch)))?;
+    writer.flush()
+}
+
+fn format_branch(branch: &BranchConcordance) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        branch.id,
+        format_value(branch.gcf),
+        format_value(branch.gdf1),
+        format_value(branch.gdf2),
+        format_value(branch.gdfp),
+        branch.gn,
+        format_value(branch.scf),
+        format_value(branch.sdf1),
+        format_value(branch.sdf2),
+        branch.sn,
+        branch
+            .support
+            .map_or_else(|| String::from("NA"), format_value),
+        branch
+            .length
+            .map_or_else(|| String::from("NA"), format_value),
+        csv_field(&branch.clade.join(";"))
+    )
+}
+
+fn write_discordant_report(report: &Path, discordant: &[BranchConcordance]) -> Result<()> {
+    let rows: Vec<String> = discordant.iter().map(format_branch).collect();
+    utils::write_report_or_remove(report, &rows, BRANCH_HEADER)
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        String::from("NA")
+    } else {
+        value.to_string()
+    }
+}
+
+fn parse_error(path: &Path, line: Option<usize>, message: &str) -> MyteError {
+    MyteError::Parse {
+        path: PathBuf::from(path),
+        line,
+        message: String::from(message),
+    }
+}
+
+fn print_concordance_info(
+    branches: &[BranchConcordance],
+    discordant: &[BranchConcordance],
+    cutoffs: &DiscordanceCutoffs,
+    table: &Path,
+    report: &Path,
+) {
+    let mean = |values: Vec<f64>| {
+        let values: Vec<f64> = values.into_iter().filter(|v| !v.is_nan()).collect();
+        if values.is_empty() {
+            f64::NAN
+        } else {
+            values.iter().sum::<f64>() / values.len() as f64
+        }
+    };
+    let gcf = mean(branches.iter().map(|branch| branch.gcf).collect());
+    let scf = mean(branches.iter().map(|branch| branch.scf).collect());
+    log::info!("{:18}: {}", "Branches", branches.len());
+    log::info!("{:18}: {:.2}", "Mean gCF", gcf);
+    log::info!("{:18}: {:.2}", "Mean sCF", scf);
+    log::info!("{:18}: {}", "Concordance table", table.display());
+    if !discordant.is_empty() {
+        log::warn!(
+            "{:18}: {} branches with support >= {} and gCF < {} (See {})",
+            "Discordant",
+            discordant.len(),
+            cutoffs.min_support,
+            cutoffs.max_gcf,
+            report.display()
+        );
+    }
+    log::info!("");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const STAT: &str = "# Concordance factor statistics\n\
+        ID\tgCF\tgCF_N\tgDF1\tgDF1_N\tgDF2\tgDF2_N\tgDFP\tgDFP_N\tgN\t\
+        sCF\tsCF_N\tsDF1\tsDF1_N\tsDF2\tsDF2_N\tsN\tLabel\tLength\n\
+        6\t30\t3\t40\t4\t20\t2\t10\t1\t10\t35.5\t35.5\t30\t30\t34.5\t34.5\t100\t100\t0.05\n\
+        5\t90\t9\t10\t1\t0\t0\t0\t0\t10\t80\t80\t10\t10\t10\t10\t100\t\t0.1\n";
+
+    #[test]
+    fn summarize_concordance_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        fs::create_dir_all(outputs.concord_dir()).unwrap();
+        fs::write(outputs.concord_stat_file(), STAT).unwrap();
+        fs::write(
+            outputs.concord_branch_file(),
+            "(A:0.1,B:0.1,((C:0.1,D:0.1)6:0.05,E:0.1)5:0.1);",
+        )
+        .unwrap();
+        fs::write(
+            outputs.concord_tree_file(),
+            "(A:0.1,B:0.1,((C:0.1,D:0.1)100/30/35.5:0.05,E:0.1)96/90/80:0.1);",
+        )
+        .unwrap();
+        let cutoffs = DiscordanceCutoffs::default();
+        let branches = summarize_concordance(&outputs, &cutoffs, false).unwrap();
+        assert_eq!(5, branches[0].id);
+        assert_eq!(Some(96.0), branches[0].support);
+        assert_eq!(vec!["C", "D", "E"], branches[0].clade);
+        assert_eq!(10, branches[1].gn);
+        assert!(branches[1].is_discordant(&cutoffs));
+        let table = fs::read_to_string(outputs.concordance_table_file()).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!("6,30,40,20,10,10,35.5,30,34.5,100,100,0.05,C;D", lines[2]);
+        let report = fs::read_to_string(outputs.discordant_branches_file()).unwrap();
+        assert_eq!(2, report.lines().count());
+        let cutoffs = DiscordanceCutoffs {
+            min_support: 100.0,
+            max_gcf: 20.0,
+        };
+        summarize_concordance(&outputs, &cutoffs, false).unwrap();
+        assert!(!outputs.discordant_branches_file().exists());
+    }
+
+    #[test]
+    fn stat_error_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("concord.cf.stat");
+        fs::write(&path, "ID\tgCF\tsCF\n1\t100\t100\n").unwrap();
+        let res = read_stat_file(&path);
+        assert!(matches!(res, Err(MyteError::Parse { line: None, .. })));
+        fs::write(&path, STAT.replace("6\t30", "x\t30")).unwrap();
+        let res = read_stat_file(&path);
+        assert!(matches!(res, Err(MyteError::Parse { line: Some(3), .. })));
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::error::{MyteError, Result};
+
+// Settings for a run. Every field is optional,
+// because CLI flags and defaults fill in the rest.
+// Unknown keys are rejected to catch typos.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "deserialize_list")]
+    pub dir: Option<Vec<String>>,
+    pub input_fmt: Option<String>,
+    pub output: Option<String>,
+    pub threads: Option<usize>,
+    pub recursive: Option<bool>,
+    #[serde(deserialize_with = "deserialize_list")]
+    pub include: Option<Vec<String>>,
+    #[serde(deserialize_with = "deserialize_list")]
+    pub exclude: Option<Vec<String>>,
+    pub loci_list: Option<String>,
+    // Taxa for rooting the final trees, from the closest to the ingroup
+    #[serde(deserialize_with = "deserialize_list")]
+    pub outgroup: Option<Vec<String>>,
+    pub species_tree: StageConfig,
+    pub gene_trees: GeneTreeConfig,
+    pub astral: StageConfig,
+    pub executables: ExecutableConfig,
+    pub concat: ConcatConfig,
+    pub trim: TrimConfig,
+    pub validation: ValidationConfig,
+    pub filter: FilterConfig,
+    pub concordance: ConcordanceConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StageConfig {
+    pub opts: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeneTreeConfig {
+    pub opts: Option<String>,
+    pub retries: Option<usize>,
+    pub fallback_opts: Option<String>,
+    pub max_failures: Option<usize>,
+    // Support threshold for collapsing gene tree branches
+    pub collapse_below: Option<f64>,
+    // Diameter ratio for removing long-branch tips
+    pub shrink: Option<f64>,
+    pub shrink_alignments: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutableConfig {
+    pub iqtree: Option<String>,
+    pub astral: Option<String>,
+    pub trimal: Option<String>,
+    pub clipkit: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcatConfig {
+    pub output_fmt: Option<String>,
+    pub partition_fmt: Option<String>,
+}
+
+// Trimming runs only when a tool is set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrimConfig {
+    pub tool: Option<String>,
+    pub trimal_opts: Option<String>,
+    pub clipkit_opts: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+    pub skip: Option<bool>,
+    pub exclude_invalid: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    pub min_taxa: Option<usize>,
+    pub min_length: Option<usize>,
+    pub min_pis: Option<usize>,
+    pub max_missing: Option<f64>,
+    pub min_occupancy: Option<f64>,
+    pub exclude_taxa: Option<String>,
+}
+
+// Cutoffs for listing a branch as discordant
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcordanceConfig {
+    pub discordant_support: Option<f64>,
+    pub discordant_gcf: Option<f64>,
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|err| MyteError::io(path, err))?;
+        let mut config = Self::parse(path, &content)?;
+        if let Some(base) = path.parent() {
+            config.resolve_paths(base);
+        }
+        Ok(config)
+    }
+
+    // Relative paths are read from the config file's directory,
+    // so the run does not depend on where it is started.
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |value: &mut String| *value = resolve_path(base, value);
+        self.dir.iter_mut().flatten().for_each(resolve);
+        self.output.iter_mut().for_each(resolve);
+        self.loci_list.iter_mut().for_each(resolve);
+        self.filter.exclude_taxa.iter_mut().for_each(resolve);
+        let exes = &mut self.executables;
+        exes.iqtree
+            .iter_mut()
+            .chain(exes.astral.iter_mut())
+            .chain(exes.trimal.iter_mut())
+            .chain(exes.clipkit.iter_mut())
+            .filter(|exe| is_exe_path(exe))
+            .for_each(resolve);
+    }
+
+    fn parse(path: &Path, content: &str) -> Result<Self> {
+        // The toml error message includes the position.
+        toml::from_str(content).map_err(|err| MyteError::Parse {
+            path: path.to_path_buf(),
+            line: None,
+            message: err.to_string(),
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap_or_default()
+    }
+}
+
+fn resolve_path(base: &Path, value: &str) -> String {
+    base.join(value).to_string_lossy().to_string()
+}
+
+// Bare executable names are looked up in PATH. Jar files
+// run through java, which reads them from the working directory.
+fn is_exe_path(exe: &str) -> bool {
+    exe.contains('/') || exe.contains(std::path::MAIN_SEPARATOR) || exe.ends_with(".jar")
+}
+
+// Keys that take a list also accept a single string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum List {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn deserialize_list<'de, D>(deserializer: D) -> std::result::Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let list = Option::<List>::deserialize(deserializer)?;
+    Ok(list.map(|list| match list {
+        List::One(value) => vec![value],
+        List::Many(values) => values,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG_NAME: &str = "myte.toml";
+
+    #[test]
+    fn config_test() {
+        let content = r#"
+            dir = "alignments"
+            threads = 4
+
+            [gene_trees]
+            opts = "-B 1000"
+            retries = 1
+
+            [concordance]
+            discordant_gcf = 33
+        "#;
+        let config = Config::parse(Path::new(CONFIG_NAME), content).unwrap();
+        assert_eq!(Some(vec![String::from("alignments")]), config.dir);
+        assert_eq!(Some(4), config.threads);
+        assert_eq!(Some(1), config.gene_trees.retries);
+        assert_eq!(None, config.species_tree.opts);
+        assert_eq!(Some(33.0), config.concordance.discordant_gcf);
+        let resolved = Config::parse(Path::new(CONFIG_NAME), &config.to_toml()).unwrap();
+        assert_eq!(config, resolved);
+    }
+
+    #[test]
+    fn config_list_test() {
+        let content = r#"
+            dir = ["exons", "uces"]
+            recursive = true
+            exclude = "uce-2*"
+        "#;
+        let config = Config::parse(Path::new(CONFIG_NAME), content).unwrap();
+        assert_eq!(2, config.dir.as_ref().unwrap().len());
+        assert_eq!(Some(vec![String::from("uce-2*")]), config.exclude);
+        let resolved = Config::parse(Path::new(CONFIG_NAME), &config.to_toml()).unwrap();
+        assert_eq!(config, resolved);
+    }
+
+    #[test]
+    fn config_paths_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("runs");
+        fs::create_dir_all(&base).unwrap();
+        let content = r#"
+            dir = ["alignments", "/data/uces"]
+            output = "results"
+
+            [executables]
+            iqtree = "iqtree2"
+            astral = "astral.5.7.8.jar"
+            trimal = "bin/trimal"
+
+            [filter]
+            exclude_taxa = "taxa.txt"
+        "#;
+        let path = base.join(CONFIG_NAME);
+        fs::write(&path, content).unwrap();
+        let config = Config::from_file(&path).unwrap();
+        let resolved = |name: &str| Some(base.join(name).to_string_lossy().to_string());
+        let dirs = config.dir.unwrap();
+        assert_eq!(resolved("alignments").as_ref(), dirs.first());
+        assert_eq!("/data/uces", dirs[1]);
+        assert_eq!(resolved("results"), config.output);
+        assert_eq!(resolved("taxa.txt"), config.filter.exclude_taxa);
+        assert_eq!(Some(String::from("iqtree2")), config.executables.iqtree);
+        assert_eq!(resolved("astral.5.7.8.jar"), config.executables.astral);
+        assert_eq!(resolved("bin/trimal"), config.executables.trimal);
+    }
+
+    #[test]
+    fn config_typo_test() {
+        let content = "dir = \"alignments\"\nopts_g = \"-B 1000\"\n";
+        let res = Config::parse(Path::new(CONFIG_NAME), content);
+        match res {
+            Err(MyteError::Parse { message, .. }) => assert!(message.contains("opts_g")),
+            _ => panic!("Expected a parse error"),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::alignment::Alignment;
+use crate::error::{MyteError, Result};
+use crate::input::InputFmt;
+use crate::tree::{self, OutputDir};
+use crate::writer::{self, Layout, OutputFmt};
+
+// Converts every alignment to the output format.
+// Returns the converted files in the input order.
+pub fn convert_alignments(
+    paths: &[PathBuf],
+    input_fmt: &InputFmt,
+    output_fmt: &OutputFmt,
+    layout: &Layout,
+    outputs: &OutputDir,
+) -> Result<Vec<PathBuf>> {
+    if paths.is_empty() {
+        return Err(MyteError::Input(String::from(
+            "Found no alignments to convert",
+        )));
+    }
+    let dir = outputs.converted_dir();
+    fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    let converted = paths
+        .par_iter()
+        .map(|path| convert_alignment(path, input_fmt, output_fmt, layout, &dir))
+        .collect::<Result<Vec<PathBuf>>>()?;
+    print_convert_info(converted.len(), output_fmt, layout, &dir);
+    Ok(converted)
+}
+
+fn convert_alignment(
+    path: &Path,
+    input_fmt: &InputFmt,
+    output_fmt: &OutputFmt,
+    layout: &Layout,
+    dir: &Path,
+) -> Result<PathBuf> {
+    let aln = Alignment::from_file(path, input_fmt)?;
+    let output = writer::get_output_path(dir, &tree::get_locus_name(path), output_fmt);
+    writer::write_alignment_with(&aln, &output, output_fmt, layout)?;
+    Ok(output)
+}
+
+fn print_convert_info(count: usize, output_fmt: &OutputFmt, layout: &Layout, dir: &Path) {
+    let layout = match (output_fmt, layout.interleaved) {
+        (OutputFmt::Fasta, _) | (_, false) => "sequential",
+        _ => "interleaved",
+    };
+    log::info!("{:18}: {}", "Converted loci", count);
+    log::info!(
+        "{:18}: {} ({})",
+        "Output format",
+        get_format_name(output_fmt),
+        layout
+    );
+    log::info!("{:18}: {}\n", "Output dir", dir.display());
+}
+
+fn get_format_name(output_fmt: &OutputFmt) -> &'static str {
+    match output_fmt {
+        OutputFmt::Fasta => "FASTA",
+        OutputFmt::Nexus => "NEXUS",
+        OutputFmt::Phylip => "relaxed PHYLIP",
+        OutputFmt::PhylipStrict => "strict PHYLIP",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let paths = vec![
+            PathBuf::from("test_files/gene_1.nexus"),
+            PathBuf::from("test_files/gene_2.nexus"),
+        ];
+        let layout = Layout {
+            interleaved: true,
+            line_width: Some(5),
+        };
+        let converted = convert_alignments(
+            &paths,
+            &InputFmt::Nexus,
+            &OutputFmt::Phylip,
+            &layout,
+            &outputs,
+        )
+        .unwrap();
+        assert_eq!(outputs.converted_dir().join("gene_1.phy"), converted[0]);
+        converted.iter().zip(paths.iter()).for_each(|(copy, path)| {
+            let aln = Alignment::from_file(path, &InputFmt::Nexus).unwrap();
+            let copy = Alignment::from_file(copy, &InputFmt::Phylip).unwrap();
+            assert_eq!(aln.taxa, copy.taxa);
+            assert_eq!(aln.sequences, copy.sequences);
+        });
+    }
+}
@@ -2,70 +2,121 @@ use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 use std::process::Command;
-use std::str;
 
 use ansi_term::Colour::Yellow;
 use regex::Regex;
 
-use crate::tree::{ASTRAL_EXE, IQTREE_EXE};
+use crate::error::{MyteError, Result};
+use crate::executor::ToolCommand;
+use crate::tree::Executables;
 
-pub fn fix_astral_dependency(path: &str) {
+pub fn fix_astral_dependency(path: &str) -> Result<()> {
     let fname = "astral.sh";
     let jar_path = Path::new(path);
     let jar_full_path = jar_path
         .canonicalize()
-        .expect("Failed getting path to the Astral jar file");
-    let mut file = File::create(&fname).expect("Failed creating a file to solve astral dependency");
-    writeln!(file, "#!/bin/bash").unwrap();
-    writeln!(
-        file,
-        "java -D\"java.library.path={}/lib\" -jar {} \"$@\"",
-        jar_full_path.parent().unwrap().to_string_lossy(),
-        jar_full_path.to_string_lossy()
-    )
-    .expect("Failed in writing an executable file for Astral");
+        .map_err(|err| MyteError::io(jar_path, err))?;
+    let jar_dir = jar_full_path.parent().unwrap_or_else(|| Path::new("/"));
+    let mut file = File::create(fname).map_err(|err| MyteError::io(fname, err))?;
+    writeln!(file, "#!/bin/bash")
+        .and_then(|_| {
+            writeln!(
+                file,
+                "java -D\"java.library.path={}/lib\" -jar {} \"$@\"",
+                jar_dir.to_string_lossy(),
+                jar_full_path.to_string_lossy()
+            )
+        })
+        .map_err(|err| MyteError::io(fname, err))?;
 
-    make_astral_executable(&fname);
+    make_astral_executable(fname)
 }
 
-pub fn check_dependencies() {
+pub fn check_dependencies(exes: &Executables) {
     log::info!("{}", Yellow.paint("Dependencies"));
-    check_iqtree();
-    check_astral();
+    check_iqtree(exes);
+    check_astral(exes);
+    check_trimmer("trimAl", &exes.trimal, exes.trimal_command());
+    check_trimmer("ClipKIT", &exes.clipkit, exes.clipkit_command());
     println!();
 }
 
-fn make_astral_executable(fname: &str) {
-    Command::new("chmod")
+fn make_astral_executable(fname: &str) -> Result<()> {
+    let status = Command::new("chmod")
         .arg("+x")
         .arg(fname)
-        .spawn()
-        .expect("CANNOT EXECUTE chmod");
+        .status()
+        .map_err(|err| MyteError::spawn("chmod", err))?;
+    if !status.success() {
+        return Err(MyteError::ToolFailed {
+            exe: String::from("chmod"),
+            input: Path::new(fname).to_path_buf(),
+            code: status.code(),
+            stderr: String::new(),
+        });
+    }
+    Ok(())
 }
 
-fn check_iqtree() {
-    let out = Command::new(IQTREE_EXE).arg("--version").output();
+fn check_iqtree(exes: &Executables) {
+    let mut cmd = exes.iqtree_command();
+    cmd.arg("--version");
+    let out = get_command(&cmd).output();
 
     match out {
+        Ok(out) => match find_version(&String::from_utf8_lossy(&out.stdout)) {
+            Some(version) => log::info!("{:18}: IQ-TREE v{} ({})", "[OK]", version, exes.iqtree),
+            None => log::info!("{:18}: IQ-TREE unknown version ({})", "[OK]", exes.iqtree),
+        },
+        Err(_) => log::info!("{:18}: IQ-TREE ({})", "[NOT FOUND]", exes.iqtree),
+    }
+}
+
+// Trimming tools are optional. They are only needed for --trim.
+fn check_trimmer(name: &str, exe: &str, mut cmd: ToolCommand) {
+    cmd.arg("--version");
+    match get_command(&cmd).output() {
         Ok(out) => {
-            let output = str::from_utf8(&out.stdout).unwrap().trim();
-            let re = Regex::new(r"(\d+\.)?(\d+\.)?(\*|\d+)")
-                .expect("Failed to setup regular expression for version numbers.");
-            let version = re
-                .find(output)
-                .expect("Cannot capture version in the stdout iqtree")
-                .as_str();
-            log::info!("{:18}: IQ-TREE v{}", "[OK]", version)
+            // Some tools print their version to stderr.
+            let output = format!(
+                "{}{}",
+                String::from_utf8_lossy(&out.stdout),
+                String::from_utf8_lossy(&out.stderr)
+            );
+            match find_version(&output) {
+                Some(version) => log::info!("{:18}: {} v{} ({})", "[OK]", name, version, exe),
+                None => log::info!("{:18}: {} unknown version ({})", "[OK]", name, exe),
+            }
         }
-        Err(_) => log::info!("{:18}: IQ-TREE", "[NOT FOUND]"),
+        Err(_) => log::info!("{:18}: {} ({}, optional)", "[NOT FOUND]", name, exe),
     }
 }
 
-fn check_astral() {
-    let out = Command::new(ASTRAL_EXE).arg("--version").output();
+fn find_version(output: &str) -> Option<String> {
+    let re = Regex::new(r"(\d+\.)?(\d+\.)?(\*|\d+)")
+        .expect("Failed to setup regular expression for version numbers.");
+    re.find(output.trim())
+        .map(|version| String::from(version.as_str()))
+}
+
+// Java starts even when the jar is missing,
+// so the jar has to exist too.
+fn check_astral(exes: &Executables) {
+    let mut cmd = exes.astral_command();
+    cmd.arg("--version");
+    let out = get_command(&cmd).output();
+    let is_jar = exes.astral.ends_with(".jar");
 
     match out {
-        Ok(_) => log::info!("{:18}: ASTRAL", "[OK]"),
-        Err(_) => log::info!("{:18}: ASTRAL", "[NOT FOUND]"),
+        Ok(_) if !is_jar || Path::new(&exes.astral).is_file() => {
+            log::info!("{:18}: ASTRAL ({})", "[OK]", exes.astral)
+        }
+        _ => log::info!("{:18}: ASTRAL ({})", "[NOT FOUND]", exes.astral),
     }
 }
+
+fn get_command(cmd: &ToolCommand) -> Command {
+    let mut command = Command::new(cmd.program());
+    command.args(cmd.args());
+    command
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub type Result<T> = std::result::Result<T, MyteError>;
+
+// Exit codes follow BSD sysexits where one fits.
+const EXIT_MISSING_EXE: i32 = 127;
+const EXIT_TOOL_FAILED: i32 = 70;
+const EXIT_IO: i32 = 74;
+const EXIT_PARSE: i32 = 65;
+const EXIT_INPUT: i32 = 66;
+
+#[derive(Debug)]
+pub enum MyteError {
+    MissingExecutable {
+        exe: String,
+        source: io::Error,
+    },
+    ToolFailed {
+        exe: String,
+        input: PathBuf,
+        code: Option<i32>,
+        stderr: String,
+    },
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+    Input(String),
+    // Gene tree estimation failed for some loci.
+    // Details are in the failure report.
+    LociFailed {
+        failed: usize,
+        report: PathBuf,
+    },
+    TooManyFailures {
+        failed: usize,
+        max: usize,
+        report: PathBuf,
+    },
+}
+
+impl MyteError {
+    pub fn io<P: AsRef<Path>>(path: P, source: io::Error) -> Self {
+        MyteError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    // Spawning fails with NotFound when the executable is not in PATH.
+    pub fn spawn(exe: &str, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => {
+                MyteError::MissingExecutable {
+                    exe: String::from(exe),
+                    source,
+                }
+            }
+            _ => MyteError::io(exe, source),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MyteError::MissingExecutable { .. } => EXIT_MISSING_EXE,
+            MyteError::ToolFailed { .. } => EXIT_TOOL_FAILED,
+            MyteError::LociFailed { .. } => EXIT_TOOL_FAILED,
+            MyteError::TooManyFailures { .. } => EXIT_TOOL_FAILED,
+            MyteError::Io { .. } => EXIT_IO,
+            MyteError::Parse { .. } => EXIT_PARSE,
+            MyteError::Input(_) => EXIT_INPUT,
+        }
+    }
+}
+
+impl fmt::Display for MyteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MyteError::MissingExecutable { exe, source } => write!(
+                f,
+                "Cannot run {} ({}). Check the installation using `myte check`",
+                exe, source
+            ),
+            MyteError::ToolFailed {
+                exe,
+                input,
+                code,
+                stderr,
+            } => {
+                write!(f, "{} failed to process {}", exe, input.display())?;
+                match code {
+                    Some(code) => write!(f, " (exit code {})", code)?,
+                    None => write!(f, " (terminated by a signal)")?,
+                }
+                if !stderr.trim().is_empty() {
+                    write!(f, ":\n{}", stderr.trim())?;
+                }
+                Ok(())
+            }
+            MyteError::Io { path, source } => {
+                write!(f, "Failed accessing {}: {}", path.display(), source)
+            }
+            MyteError::Parse {
+                path,
+                line,
+                message,
+            } => match line {
+                Some(line) => write!(f, "{}:{}: {}", path.display(), line, message),
+                None => write!(f, "{}: {}", path.display(), message),
+            },
+            MyteError::Input(message) => write!(f, "{}", message),
+            MyteError::LociFailed { failed, report } => write!(
+                f,
+                "IQ-TREE failed for {} loci. See {}",
+                failed,
+                report.display()
+            ),
+            MyteError::TooManyFailures {
+                failed,
+                max,
+                report,
+            } => write!(
+                f,
+                "Aborted gene tree estimation after {} loci failed (max failures: {}). See {}",
+                failed,
+                max,
+                report.display()
+            ),
+        }
+    }
+}
+
+impl Error for MyteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MyteError::MissingExecutable { source, .. } => Some(source),
+            MyteError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spawn_error_test() {
+        let err = io::Error::new(io::ErrorKind::NotFound, "not found");
+        let err = MyteError::spawn("iqtree2", err);
+        assert_eq!(EXIT_MISSING_EXE, err.exit_code());
+    }
+
+    #[test]
+    fn parse_error_display_test() {
+        let err = MyteError::Parse {
+            path: PathBuf::from("gene_1.nexus"),
+            line: Some(4),
+            message: String::from("Unexpected end of file"),
+        };
+        assert_eq!("gene_1.nexus:4: Unexpected end of file", err.to_string());
+    }
+}
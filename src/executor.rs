@@ -0,0 +1,255 @@
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::Result;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A command line for an external tool.
+// Arguments are kept as strings, so the command
+// can be logged and compared before it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ToolCommand {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: String::from(program),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.args.push(arg.as_ref().to_string_lossy().to_string());
+        self
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+}
+
+impl fmt::Display for ToolCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        self.args.iter().try_for_each(|arg| write!(f, " {}", arg))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutput {
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ToolOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+// Runs external tools for the pipeline.
+// Gene trees are estimated in parallel,
+// hence executors must be shareable across threads.
+pub trait Executor: Sync {
+    fn execute(&self, command: &ToolCommand) -> Result<ToolOutput>;
+
+    // Dry runs only print commands.
+    // Stages use it to avoid touching the file system.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+pub struct SystemExecutor;
+
+impl Executor for SystemExecutor {
+    fn execute(&self, command: &ToolCommand) -> Result<ToolOutput> {
+        let out = Command::new(command.program())
+            .args(command.args())
+            .output()?;
+        Ok(ToolOutput {
+            code: out.status.code(),
+            stdout: String::from_utf8_lossy(&out.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&out.stderr).to_string(),
+        })
+    }
+}
+
+// Prints every command in the order it would run.
+#[derive(Default)]
+pub struct DryRunExecutor {
+    counts: AtomicUsize,
+}
+
+impl DryRunExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Executor for DryRunExecutor {
+    fn execute(&self, command: &ToolCommand) -> Result<ToolOutput> {
+        let count = self.counts.fetch_add(1, Ordering::SeqCst) + 1;
+        log::info!("[{}] {}", count, command);
+        Ok(ToolOutput {
+            code: Some(0),
+            ..ToolOutput::default()
+        })
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    pub const MOCK_TREE: &str = "(A:0.1,B:0.2,(C:0.3,D:0.4)100:0.5);";
+    const MOCK_CF_BRANCH: &str = "(A:0.1,B:0.2,(C:0.3,D:0.4)5:0.5);";
+    const MOCK_CF_STAT: &str = "ID\tgCF\tgCF_N\tgDF1\tgDF1_N\tgDF2\tgDF2_N\tgDFP\tgDFP_N\tgN\t\
+        sCF\tsCF_N\tsDF1\tsDF1_N\tsDF2\tsDF2_N\tsN\tLabel\tLength\n\
+        5\t100\t2\t0\t0\t0\t0\t0\t0\t2\t90\t90\t5\t5\t5\t5\t100\t100\t0.5\n";
+
+    impl ToolCommand {
+        // Returns the value that follows a flag, e.g. the path after `--prefix`.
+        pub fn value_of(&self, flag: &str) -> Option<&str> {
+            self.args
+                .iter()
+                .position(|arg| arg == flag)
+                .and_then(|i| self.args.get(i + 1))
+                .map(|value| value.as_str())
+        }
+
+        pub fn has_flag(&self, flag: &str) -> bool {
+            self.args.iter().any(|arg| arg == flag)
+        }
+    }
+
+    // Records every command line and writes canned outputs
+    // where IQ-TREE and ASTRAL would have written theirs.
+    #[derive(Default)]
+    pub struct MockExecutor {
+        commands: Mutex<Vec<ToolCommand>>,
+        failing: Vec<String>,
+    }
+
+    impl MockExecutor {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        // IQ-TREE fails for commands with an argument containing any of the names,
+        // e.g. an alignment path or a param.
+        pub fn failing(names: &[&str]) -> Self {
+            Self {
+                failing: names.iter().map(|name| String::from(*name)).collect(),
+                ..Self::default()
+            }
+        }
+
+        pub fn commands(&self) -> Vec<ToolCommand> {
+            self.commands.lock().unwrap().clone()
+        }
+
+        fn write_iqtree_files(&self, command: &ToolCommand) -> Result<()> {
+            let prefix = match command.value_of("--prefix") {
+                Some(prefix) => prefix,
+                None => return Ok(()),
+            };
+            if command.has_flag("--gcf") {
+                fs::write(format!("{}.cf.tree", prefix), MOCK_TREE)?;
+                fs::write(format!("{}.cf.branch", prefix), MOCK_CF_BRANCH)?;
+                fs::write(format!("{}.cf.stat", prefix), MOCK_CF_STAT)?;
+            } else {
+                fs::write(format!("{}.treefile", prefix), MOCK_TREE)?;
+                fs::write(
+                    format!("{}.iqtree", prefix),
+                    "Total wall-clock time used: 0.0 seconds\n",
+                )?;
+            }
+            fs::write(format!("{}.log", prefix), command.to_string())
+        }
+
+        fn should_fail(&self, command: &ToolCommand) -> bool {
+            // Only IQ-TREE commands have a prefix.
+            command.has_flag("--prefix")
+                && self
+                    .failing
+                    .iter()
+                    .any(|name| command.args().iter().any(|arg| arg.contains(name)))
+        }
+
+        // Trimmed alignments are copies of the input.
+        // trimAl takes the input after `-in`, ClipKIT as the first argument.
+        fn write_trimmed_files(&self, command: &ToolCommand) -> Result<()> {
+            let input = command
+                .value_of("-in")
+                .or_else(|| command.args().first().map(|arg| arg.as_str()));
+            let output = command.value_of("-out").or_else(|| command.value_of("-o"));
+            match (input, output) {
+                (Some(input), Some(output)) => fs::copy(input, output).map(|_| ()),
+                _ => Ok(()),
+            }
+        }
+
+        fn write_astral_files(&self, command: &ToolCommand) -> Result<()> {
+            match command.value_of("-o") {
+                Some(output) => fs::write(Path::new(output), MOCK_TREE),
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl Executor for MockExecutor {
+        fn execute(&self, command: &ToolCommand) -> Result<ToolOutput> {
+            self.commands.lock().unwrap().push(command.clone());
+            if self.should_fail(command) {
+                return Ok(ToolOutput {
+                    code: Some(2),
+                    stdout: String::new(),
+                    stderr: String::from("ERROR: Mock alignment error\nERROR: Mock exit"),
+                });
+            }
+            if command.has_flag("--prefix") {
+                self.write_iqtree_files(command)?;
+            } else if command.has_flag("-fasta") || command.has_flag("-of") {
+                self.write_trimmed_files(command)?;
+            } else {
+                self.write_astral_files(command)?;
+            }
+            Ok(ToolOutput {
+                code: Some(0),
+                stdout: String::new(),
+                stderr: String::from("Mock ASTRAL log"),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tool_command_test() {
+        let mut cmd = ToolCommand::new("iqtree2");
+        cmd.arg("-s").arg("genes").arg("--prefix").arg("concat");
+        assert_eq!("iqtree2 -s genes --prefix concat", cmd.to_string());
+        assert_eq!(Some("concat"), cmd.value_of("--prefix"));
+        assert_eq!(None, cmd.value_of("-T"));
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ansi_term::Colour::Yellow;
+use rayon::iter::Either;
+use rayon::prelude::*;
+
+use crate::alignment::Alignment;
+use crate::error::{MyteError, Result};
+use crate::input::InputFmt;
+use crate::summary::{self, LocusSummary};
+use crate::tree::{self, OutputDir};
+use crate::utils;
+use crate::writer::{self, OutputFmt};
+
+// Loci left with fewer taxa after dropping taxa are excluded,
+// because IQ-TREE fails on them.
+const MIN_TAXA_LEFT: usize = 4;
+
+// Loci below any threshold are excluded. Unset thresholds are not checked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocusThresholds {
+    pub min_taxa: Option<usize>,
+    pub min_length: Option<usize>,
+    pub min_pis: Option<usize>,
+    // Proportion of gaps and missing data, from 0 to 1
+    pub max_missing: Option<f64>,
+}
+
+impl LocusThresholds {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Returns every threshold the locus fails.
+    fn check(&self, locus: &LocusSummary) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if let Some(min) = self.min_taxa {
+            if locus.taxa.len() < min {
+                reasons.push(format!("{} taxa < min taxa {}", locus.taxa.len(), min));
+            }
+        }
+        if let Some(min) = self.min_length {
+            if locus.nchar < min {
+                reasons.push(format!("{} sites < min length {}", locus.nchar, min));
+            }
+        }
+        if let Some(min) = self.min_pis {
+            if locus.informative_sites < min {
+                reasons.push(format!(
+                    "{} informative sites < min PIS {}",
+                    locus.informative_sites, min
+                ));
+            }
+        }
+        if let Some(max) = self.max_missing {
+            if locus.missing_ratio() > max {
+                reasons.push(format!(
+                    "{:.4} missing data > max missing {}",
+                    locus.missing_ratio(),
+                    max
+                ));
+            }
+        }
+        reasons
+    }
+}
+
+// Returns the loci that pass all thresholds.
+// The excluded loci are logged and written to a report.
+pub fn filter_loci(
+    paths: &[PathBuf],
+    input_fmt: &InputFmt,
+    thresholds: &LocusThresholds,
+    outputs: &OutputDir,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let summaries = summary::summarize_loci(paths, input_fmt)?;
+    let mut kept = Vec::with_capacity(summaries.len());
+    let mut excluded = Vec::new();
+    summaries.into_iter().for_each(|locus| {
+        let reasons = thresholds.check(&locus);
+        if reasons.is_empty() {
+            kept.push(locus.path);
+        } else {
+            excluded.push((locus.locus, reasons.join("; ")));
+        }
+    });
+    let report = outputs.filtered_loci_file();
+    if !dry_run {
+        write_report(&report, &excluded)?;
+    }
+    print_filter_info(paths.len(), &excluded, &report);
+    Ok(kept)
+}
+
+fn write_report(report: &Path, excluded: &[(String, String)]) -> Result<()> {
+    let rows: Vec<String> = excluded
+        .iter()
+        .map(|(locus, reason)| format!("{}\t{}", locus, reason))
+        .collect();
+    utils::write_report_or_remove(report, &rows, "locus\treason")
+}
+
+fn print_filter_info(num_aln: usize, excluded: &[(String, String)], report: &Path) {
+    log::info!("{:18}: {}", "Alignments", num_aln);
+    log::info!("{:18}: {}", "Kept loci", num_aln - excluded.len());
+    log::info!("{:18}: {}", "Excluded loci", excluded.len());
+    excluded.iter().for_each(|(locus, reason)| {
+        log::warn!(
+            "{}: Excluded {} ({})",
+            Yellow.paint("WARNING"),
+            locus,
+            reason
+        )
+    });
+    if !excluded.is_empty() {
+        log::info!("{:18}: {}", "Report", report.display());
+    }
+    println!();
+}
+
+// Taxa dropped from every alignment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaxonFilter {
+    // Percentage of loci, from 0 to 100
+    pub min_occupancy: Option<f64>,
+    pub excluded: HashSet<String>,
+}
+
+impl TaxonFilter {
+    // The exclusion file lists one taxon per line. Lines starting with # are skipped.
+    pub fn new(min_occupancy: Option<f64>, exclude_file: Option<&Path>) -> Result<Self> {
+        let excluded = match exclude_file {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|err| MyteError::io(path, err))?
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect(),
+            None => HashSet::new(),
+        };
+        Ok(Self {
+            min_occupancy,
+            excluded,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_occupancy.is_none() && self.excluded.is_empty()
+    }
+
+    fn check(&self, taxon: &str, occupancy: f64) -> Option<String> {
+        if self.excluded.contains(taxon) {
+            return Some(String::from("Listed in the exclusion file"));
+        }
+        match self.min_occupancy {
+            Some(min) if occupancy < min => Some(format!(
+                "Present in {:.2}% of loci < min occupancy {}%",
+                occupancy, min
+            )),
+            _ => None,
+        }
+    }
+}
+
+struct DroppedTaxon {
+    taxon: String,
+    loci: usize,
+    occupancy: f64,
+    reason: String,
+}
+
+// Drops taxa from every alignment and writes the alignments
+// to the filtered alignment directory. Returns None when no taxa
+// are dropped, so the analyses keep using the input files.
+// Runs before the locus thresholds, so they check the taxa left.
+pub fn filter_taxa(
+    paths: &[PathBuf],
+    input_fmt: &InputFmt,
+    taxon_filter: &TaxonFilter,
+    outputs: &OutputDir,
+    dry_run: bool,
+) -> Result<Option<Vec<PathBuf>>> {
+    let alignments = paths
+        .par_iter()
+        .map(|path| Alignment::from_file(path, input_fmt))
+        .collect::<Result<Vec<Alignment>>>()?;
+    let dropped = get_dropped_taxa(&alignments, taxon_filter);
+    let report = outputs.excluded_taxa_file();
+    if !dry_run {
+        write_taxa_report(&report, &dropped)?;
+    }
+    print_taxa_info(&alignments, &dropped, taxon_filter, &report);
+    if dropped.is_empty() {
+        return Ok(None);
+    }
+    let dir = outputs.taxon_filtered_dir();
+    if !dry_run {
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+        }
+        fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    }
+    let dropped: HashSet<&str> = dropped.iter().map(|taxon| taxon.taxon.as_str()).collect();
+    let output_fmt = get_output_fmt(input_fmt);
+    let (filtered, excluded): (Vec<Alignment>, Vec<Alignment>) = alignments
+        .into_par_iter()
+        .map(|aln| {
+            let ntax = aln.taxa.len();
+            (remove_taxa(aln, &dropped), ntax)
+        })
+        .partition_map(|(aln, ntax)| {
+            if aln.taxa.len() < ntax && aln.taxa.len() < MIN_TAXA_LEFT {
+                Either::Right(aln)
+            } else {
+                Either::Left(aln)
+            }
+        });
+    excluded.iter().for_each(|aln| {
+        log::warn!(
+            "{}: Excluded {} ({} taxa left)",
+            Yellow.paint("WARNING"),
+            tree::get_locus_name(&aln.path),
+            aln.taxa.len()
+        )
+    });
+    let paths = filtered
+        .par_iter()
+        .map(|aln| {
+            let path = writer::get_output_path(&dir, &tree::get_locus_name(&aln.path), &output_fmt);
+            if !dry_run {
+                writer::write_alignment(aln, &path, &output_fmt)?;
+            }
+            Ok(path)
+        })
+        .collect::<Result<Vec<PathBuf>>>()?;
+    log::info!(
+        "{:18}: {} alignments in {}\n",
+        "Filtered dir",
+        paths.len(),
+        dir.display()
+    );
+    Ok(Some(paths))
+}
+
+// Occupancy is the percentage of loci containing the taxon.
+fn get_dropped_taxa(alignments: &[Alignment], taxon_filter: &TaxonFilter) -> Vec<DroppedTaxon> {
+    let mut loci: BTreeMap<&str, usize> = BTreeMap::new();
+    alignments
+        .iter()
+        .flat_map(|aln| aln.taxa.iter())
+        .for_each(|taxon| *loci.entry(taxon).or_insert(0) += 1);
+    loci.into_iter()
+        .filter_map(|(taxon, count)| {
+            let occupancy = count as f64 * 100.0 / alignments.len() as f64;
+            taxon_filter
+                .check(taxon, occupancy)
+                .map(|reason| DroppedTaxon {
+                    taxon: String::from(taxon),
+                    loci: count,
+                    occupancy,
+                    reason,
+                })
+        })
+        .collect()
+}
+
+pub fn remove_taxa(aln: Alignment, dropped: &HashSet<&str>) -> Alignment {
+    let (taxa, sequences) = aln
+        .taxa
+        .into_iter()
+        .zip(aln.sequences)
+        .filter(|(taxon, _)| !dropped.contains(taxon.as_str()))
+        .unzip();
+    Alignment {
+        taxa,
+        sequences,
+        ..aln
+    }
+}
+
+// Files keep the input format, so the analyses parse them the same way.
+// Detected formats are written as FASTA.
+pub fn get_output_fmt(input_fmt: &InputFmt) -> OutputFmt {
+    match input_fmt {
+        InputFmt::Nexus => OutputFmt::Nexus,
+        InputFmt::Phylip => OutputFmt::Phylip,
+        InputFmt::PhylipStrict => OutputFmt::PhylipStrict,
+        InputFmt::Fasta | InputFmt::Auto => OutputFmt::Fasta,
+    }
+}
+
+fn write_taxa_report(report: &Path, dropped: &[DroppedTaxon]) -> Result<()> {
+    let rows: Vec<String> = dropped
+        .iter()
+        .map(|taxon| {
+            format!(
+                "{}\t{}\t{:.2}\t{}",
+                taxon.taxon, taxon.loci, taxon.occupancy, taxon.reason
+            )
+        })
+        .collect();
+    utils::write_report_or_remove(report, &rows, "taxon\tloci\toccupancy\treason")
+}
+
+fn print_taxa_info(
+    alignments: &[Alignment],
+    dropped: &[DroppedTaxon],
+    taxon_filter: &TaxonFilter,
+    report: &Path,
+) {
+    let taxa: HashSet<&str> = alignments
+        .iter()
+        .flat_map(|aln| aln.taxa.iter().map(|taxon| taxon.as_str()))
+        .collect();
+    log::info!("{:18}: {}", "Taxa", taxa.len());
+    log::info!("{:18}: {}", "Dropped taxa", dropped.len());
+    dropped.iter().for_each(|taxon| {
+        log::warn!(
+            "{}: Dropped {} ({})",
+            Yellow.paint("WARNING"),
+            taxon.taxon,
+            taxon.reason
+        )
+    });
+    let mut missing: Vec<&String> = taxon_filter
+        .excluded
+        .iter()
+        .filter(|taxon| !taxa.contains(taxon.as_str()))
+        .collect();
+    missing.sort();
+    missing.iter().for_each(|taxon| {
+        log::warn!(
+            "{}: Excluded taxon {} is not in any alignment",
+            Yellow.paint("WARNING"),
+            taxon
+        )
+    });
+    if !dropped.is_empty() {
+        log::info!("{:18}: {}", "Report", report.display());
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filter_loci_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let short = dir.path().join("short.fas");
+        fs::write(&short, ">a\nAC\n>b\nAG\n>c\nAC\n>d\nAG\n").unwrap();
+        let gappy = dir.path().join("gappy.fas");
+        fs::write(&gappy, ">a\nACGT\n>b\nA---\n>c\nACGT\n>d\nA---\n").unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let thresholds = LocusThresholds {
+            min_taxa: Some(4),
+            min_length: Some(3),
+            max_missing: Some(0.25),
+            ..LocusThresholds::default()
+        };
+        let paths = vec![short, gappy];
+        let kept = filter_loci(&paths, &InputFmt::Fasta, &thresholds, &outputs, false).unwrap();
+        assert!(kept.is_empty());
+        let report = fs::read_to_string(outputs.filtered_loci_file()).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!("short\t2 sites < min length 3", lines[2]);
+
+        let thresholds = LocusThresholds {
+            min_pis: Some(1),
+            ..LocusThresholds::default()
+        };
+        let kept = filter_loci(&paths, &InputFmt::Fasta, &thresholds, &outputs, false).unwrap();
+        assert_eq!(vec![dir.path().join("short.fas")], kept);
+    }
+
+    #[test]
+    fn filter_taxa_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let gene_1 = dir.path().join("gene_1.fas");
+        fs::write(
+            &gene_1,
+            ">a\nACGT\n>b\nAC-T\n>c\nACGA\n>e\nACGA\n>f\nACGT\n",
+        )
+        .unwrap();
+        let gene_2 = dir.path().join("gene_2.fas");
+        fs::write(&gene_2, ">a\nGG\n>b\nGC\n>e\nGG\n>f\nGC\n").unwrap();
+        let gene_3 = dir.path().join("gene_3.fas");
+        fs::write(&gene_3, ">d\nTT\n").unwrap();
+        let exclude = dir.path().join("exclude.txt");
+        fs::write(&exclude, "# failed samples\nd\n").unwrap();
+        let outputs = OutputDir::new(dir.path().join("results"));
+        fs::create_dir_all(outputs.root()).unwrap();
+        let taxon_filter = TaxonFilter::new(Some(50.0), Some(&exclude)).unwrap();
+        let paths = vec![gene_1, gene_2, gene_3];
+        let filtered = filter_taxa(&paths, &InputFmt::Fasta, &taxon_filter, &outputs, false)
+            .unwrap()
+            .unwrap();
+        let filtered_dir = outputs.taxon_filtered_dir();
+        assert_eq!(
+            vec![
+                filtered_dir.join("gene_1.fas"),
+                filtered_dir.join("gene_2.fas")
+            ],
+            filtered
+        );
+        let aln = Alignment::from_file(&filtered[0], &InputFmt::Fasta).unwrap();
+        assert_eq!(vec!["a", "b", "e", "f"], aln.taxa);
+        let report = fs::read_to_string(outputs.excluded_taxa_file()).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(3, lines.len());
+        assert!(lines[1].starts_with("c\t1\t33.33\t"));
+        assert_eq!("d\t1\t33.33\tListed in the exclusion file", lines[2]);
+
+        let taxon_filter = TaxonFilter::new(Some(30.0), None).unwrap();
+        let res = filter_taxa(&paths, &InputFmt::Fasta, &taxon_filter, &outputs, false).unwrap();
+        assert!(res.is_none());
+        assert!(!outputs.excluded_taxa_file().exists());
+    }
+
+    // Locus thresholds run on the alignments left after dropping taxa.
+    #[test]
+    fn filter_taxa_min_taxa_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let gene_1 = dir.path().join("gene_1.fas");
+        fs::write(&gene_1, ">a\nAC\n>b\nAT\n>c\nAC\n>d\nAT\n>f\nAC\n").unwrap();
+        let gene_2 = dir.path().join("gene_2.fas");
+        fs::write(&gene_2, ">a\nGG\n>b\nGC\n>c\nGG\n>d\nGC\n>e\nGG\n").unwrap();
+        let gene_3 = dir.path().join("gene_3.fas");
+        fs::write(&gene_3, ">a\nTT\n>b\nTA\n>c\nTT\n>d\nTA\n>e\nTT\n").unwrap();
+        let outputs = OutputDir::new(dir.path().join("results"));
+        fs::create_dir_all(outputs.root()).unwrap();
+        let taxon_filter = TaxonFilter::new(Some(50.0), None).unwrap();
+        let paths = vec![gene_1, gene_2, gene_3];
+        let filtered = filter_taxa(&paths, &InputFmt::Fasta, &taxon_filter, &outputs, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(3, filtered.len());
+        let thresholds = LocusThresholds {
+            min_taxa: Some(5),
+            ..LocusThresholds::default()
+        };
+        let kept = filter_loci(&filtered, &InputFmt::Fasta, &thresholds, &outputs, false).unwrap();
+        assert_eq!(filtered[1..], kept[..]);
+        let report = fs::read_to_string(outputs.filtered_loci_file()).unwrap();
+        assert_eq!(
+            "gene_1\t4 taxa < min taxa 5",
+            report.lines().nth(1).unwrap()
+        );
+
+        // IQ-TREE fails on loci left with fewer than four taxa.
+        let exclude = dir.path().join("exclude.txt");
+        fs::write(&exclude, "c\nd\n").unwrap();
+        let taxon_filter = TaxonFilter::new(None, Some(&exclude)).unwrap();
+        let filtered = filter_taxa(&paths, &InputFmt::Fasta, &taxon_filter, &outputs, false)
+            .unwrap()
+            .unwrap();
+        assert!(filtered.is_empty());
+    }
+}
@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use glob::Pattern;
+use regex::Regex;
+
+use crate::error::{MyteError, Result};
+use crate::tree::get_locus_name;
+
+const FASTA_EXT: [&str; 7] = ["fa", "fas", "fasta", "fna", "faa", "fsa", "afa"];
+const NEXUS_EXT: [&str; 3] = ["nex", "nexus", "nxs"];
+const PHYLIP_EXT: [&str; 2] = ["phy", "phylip"];
+// Marks a locus filter as a regular expression
+const REGEX_PREFIX: &str = "re:";
+// Number of unmatched files listed in the log
+const UNMATCHED_LOG_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFmt {
+    Auto,
+    Fasta,
+    Nexus,
+    Phylip,
+    PhylipStrict,
+}
+
+impl InputFmt {
+    // Detects the format from the first line of an alignment.
+    pub fn detect(content: &str) -> Option<Self> {
+        let line = content
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty())?;
+        if line.starts_with('>') {
+            return Some(InputFmt::Fasta);
+        }
+        if line.to_lowercase().starts_with("#nexus") {
+            return Some(InputFmt::Nexus);
+        }
+        // Headers may end with flags, such as `i` for interleaved.
+        let counts = line
+            .split_whitespace()
+            .take_while(|value| value.parse::<usize>().is_ok())
+            .count();
+        if counts >= 2 {
+            return Some(InputFmt::Phylip);
+        }
+        None
+    }
+
+    fn extensions(&self) -> Vec<&'static str> {
+        match self {
+            InputFmt::Auto => [&FASTA_EXT[..], &NEXUS_EXT[..], &PHYLIP_EXT[..]].concat(),
+            InputFmt::Fasta => FASTA_EXT.to_vec(),
+            InputFmt::Nexus => NEXUS_EXT.to_vec(),
+            InputFmt::Phylip | InputFmt::PhylipStrict => PHYLIP_EXT.to_vec(),
+        }
+    }
+}
+
+// Alignments and other files found in the input directories.
+#[derive(Debug, Default)]
+pub struct InputFiles {
+    pub alignments: Vec<PathBuf>,
+    pub unmatched: Vec<PathBuf>,
+    // Alignments left out by the loci filter
+    pub filtered: usize,
+}
+
+#[derive(Default)]
+pub struct InputOptions {
+    pub recursive: bool,
+    pub filter: LociFilter,
+    // Output directories are skipped when searching recursively.
+    pub skip_dirs: Vec<PathBuf>,
+}
+
+// Selects loci by name. Patterns are globs,
+// unless prefixed with `re:` for regular expressions.
+#[derive(Default)]
+pub struct LociFilter {
+    include: Vec<NamePattern>,
+    exclude: Vec<NamePattern>,
+    loci: Option<HashSet<String>>,
+}
+
+impl LociFilter {
+    pub fn new(include: &[String], exclude: &[String], loci_list: Option<&Path>) -> Result<Self> {
+        let loci = match loci_list {
+            Some(path) => Some(read_loci_list(path)?),
+            None => None,
+        };
+        Ok(Self {
+            include: parse_patterns(include)?,
+            exclude: parse_patterns(exclude)?,
+            loci,
+        })
+    }
+
+    fn is_match(&self, locus: &str) -> bool {
+        if let Some(loci) = &self.loci {
+            if !loci.contains(locus) {
+                return false;
+            }
+        }
+        let is_included =
+            self.include.is_empty() || self.include.iter().any(|pat| pat.is_match(locus));
+        is_included && !self.exclude.iter().any(|pat| pat.is_match(locus))
+    }
+
+    // Loci in the list are expected to exist. A missing one is likely a typo.
+    fn check_listed_loci(&self, alignments: &[PathBuf]) -> Result<()> {
+        let loci = match &self.loci {
+            Some(loci) => loci,
+            None => return Ok(()),
+        };
+        let found: HashSet<String> = alignments
+            .iter()
+            .map(|path| get_locus_name(path).to_string())
+            .collect();
+        let mut missing: Vec<&str> = loci
+            .iter()
+            .filter(|locus| !found.contains(*locus))
+            .map(|locus| locus.as_str())
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        missing.sort_unstable();
+        Err(MyteError::Input(format!(
+            "Found no alignments for {} locus/loci in the loci list: {}",
+            missing.len(),
+            missing.join(", ")
+        )))
+    }
+}
+
+enum NamePattern {
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl NamePattern {
+    fn parse(pattern: &str) -> Result<Self> {
+        match pattern.strip_prefix(REGEX_PREFIX) {
+            Some(re) => Regex::new(re)
+                .map(NamePattern::Regex)
+                .map_err(|err| MyteError::Input(format!("Invalid locus regex {}: {}", re, err))),
+            None => Pattern::new(pattern).map(NamePattern::Glob).map_err(|err| {
+                MyteError::Input(format!("Invalid locus pattern {}: {}", pattern, err))
+            }),
+        }
+    }
+
+    fn is_match(&self, locus: &str) -> bool {
+        match self {
+            NamePattern::Glob(pattern) => pattern.matches(locus),
+            NamePattern::Regex(re) => re.is_match(locus),
+        }
+    }
+}
+
+// Alignments are sorted, so loci are always processed
+// and reported in the same order. Hidden files are ignored.
+pub fn find_alignments(
+    dirs: &[String],
+    input_fmt: &InputFmt,
+    opts: &InputOptions,
+) -> Result<InputFiles> {
+    let skip_dirs: Vec<PathBuf> = opts
+        .skip_dirs
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .collect();
+    let mut paths = Vec::new();
+    dirs.iter()
+        .try_for_each(|dir| collect_files(Path::new(dir), opts, &skip_dirs, &mut paths))?;
+    paths.sort();
+    paths.dedup();
+    let mut files = InputFiles::default();
+    let mut alignments = Vec::new();
+    for path in paths {
+        if is_alignment(&path, input_fmt)? {
+            alignments.push(path);
+        } else {
+            files.unmatched.push(path);
+        }
+    }
+    check_duplicate_loci(&alignments)?;
+    opts.filter.check_listed_loci(&alignments)?;
+    let total = alignments.len();
+    files.alignments = alignments
+        .into_iter()
+        .filter(|path| opts.filter.is_match(&get_locus_name(path)))
+        .collect();
+    files.filtered = total - files.alignments.len();
+    print_unmatched_files(&files.unmatched);
+    print_filter_info(total, files.filtered);
+    Ok(files)
+}
+
+fn collect_files(
+    dir: &Path,
+    opts: &InputOptions,
+    skip_dirs: &[PathBuf],
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let entries = fs::read_dir(dir).map_err(|err| MyteError::io(dir, err))?;
+    for entry in entries {
+        let path = entry.map_err(|err| MyteError::io(dir, err))?.path();
+        if is_hidden(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            if opts.recursive && !is_skipped_dir(&path, skip_dirs) {
+                collect_files(&path, opts, skip_dirs, files)?;
+            }
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_skipped_dir(dir: &Path, skip_dirs: &[PathBuf]) -> bool {
+    match dir.canonicalize() {
+        Ok(dir) => skip_dirs.contains(&dir),
+        Err(_) => false,
+    }
+}
+
+// Output files are named after the locus,
+// so loci must be unique across directories.
+fn check_duplicate_loci(alignments: &[PathBuf]) -> Result<()> {
+    let mut loci: HashMap<String, &Path> = HashMap::new();
+    for path in alignments {
+        let locus = get_locus_name(path).to_string();
+        if let Some(other) = loci.insert(locus.clone(), path) {
+            return Err(MyteError::Input(format!(
+                "Duplicate locus name {} in {} and {}",
+                locus,
+                other.display(),
+                path.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<NamePattern>> {
+    patterns
+        .iter()
+        .map(|pattern| NamePattern::parse(pattern))
+        .collect()
+}
+
+// One locus per line. Names may include the file extension.
+// Empty lines and lines starting with # are ignored.
+fn read_loci_list(path: &Path) -> Result<HashSet<String>> {
+    let content = fs::read_to_string(path).map_err(|err| MyteError::io(path, err))?;
+    let all_ext = InputFmt::Auto.extensions();
+    let loci = content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let name = Path::new(line);
+            match name.extension() {
+                Some(ext) if all_ext.contains(&ext.to_string_lossy().to_lowercase().as_str()) => {
+                    get_locus_name(name).to_string()
+                }
+                _ => String::from(line),
+            }
+        })
+        .collect();
+    Ok(loci)
+}
+
+// Auto detection also checks the content, so a file
+// with an alignment extension but other data is skipped.
+fn is_alignment(path: &Path, input_fmt: &InputFmt) -> Result<bool> {
+    let ext = match path.extension() {
+        Some(ext) => ext.to_string_lossy().to_lowercase(),
+        None => return Ok(false),
+    };
+    if !input_fmt.extensions().contains(&ext.as_str()) {
+        return Ok(false);
+    }
+    match input_fmt {
+        InputFmt::Auto => Ok(sniff_format(path)?.is_some()),
+        _ => Ok(true),
+    }
+}
+
+// Reads only up to the first non-empty line.
+pub fn sniff_format(path: &Path) -> Result<Option<InputFmt>> {
+    let file = File::open(path).map_err(|err| MyteError::io(path, err))?;
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            // Binary files are not alignments.
+            Err(_) => return Ok(None),
+        };
+        if !line.trim().is_empty() {
+            return Ok(InputFmt::detect(&line));
+        }
+    }
+    Ok(None)
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+fn print_filter_info(total: usize, filtered: usize) {
+    if filtered == 0 {
+        return;
+    }
+    log::info!(
+        "{:18}: {} of {} alignments",
+        "Selected loci",
+        total - filtered,
+        total
+    );
+}
+
+fn print_unmatched_files(unmatched: &[PathBuf]) {
+    if unmatched.is_empty() {
+        return;
+    }
+    log::warn!(
+        "{:18}: {} file(s) are not alignments and will be skipped",
+        "Unmatched files",
+        unmatched.len()
+    );
+    unmatched
+        .iter()
+        .take(UNMATCHED_LOG_LIMIT)
+        .for_each(|path| log::warn!("{:18}  {}", "", path.display()));
+    if unmatched.len() > UNMATCHED_LOG_LIMIT {
+        log::warn!(
+            "{:18}  ... and {} more",
+            "",
+            unmatched.len() - UNMATCHED_LOG_LIMIT
+        );
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tree::OutputDir;
+
+    fn find(dir: &Path, input_fmt: &InputFmt, opts: &InputOptions) -> InputFiles {
+        find_alignments(&[dir.to_string_lossy().to_string()], input_fmt, opts).unwrap()
+    }
+
+    #[test]
+    fn find_alignments_test() {
+        let files = find(
+            Path::new("test_files"),
+            &InputFmt::Nexus,
+            &InputOptions::default(),
+        );
+        assert_eq!(2, files.alignments.len());
+        assert_eq!(Path::new("test_files/gene_1.nexus"), files.alignments[0]);
+    }
+
+    #[test]
+    fn find_mixed_alignments_test() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.fas"), ">a\nACGT\n").unwrap();
+        fs::write(dir.path().join("b.phy"), "1 4\na ACGT\n").unwrap();
+        fs::write(dir.path().join("c.nex"), "#NEXUS\n").unwrap();
+        fs::write(dir.path().join("a.fa.fai"), "a\t4\t3\n").unwrap();
+        fs::write(dir.path().join("b.phy.bak"), "1 4\na ACGT\n").unwrap();
+        fs::write(dir.path().join("d.fasta"), "locus\tlength\n").unwrap();
+        let opts = InputOptions::default();
+        let files = find(dir.path(), &InputFmt::Auto, &opts);
+        assert_eq!(3, files.alignments.len());
+        assert_eq!(3, files.unmatched.len());
+        let fasta = find(dir.path(), &InputFmt::Fasta, &opts);
+        assert_eq!(2, fasta.alignments.len());
+    }
+
+    #[test]
+    fn find_recursive_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        ["exons", "uces", ".hidden"].iter().for_each(|name| {
+            let sub = dir.path().join(name);
+            fs::create_dir_all(&sub).unwrap();
+            fs::write(sub.join(format!("{}_1.fas", name)), ">a\nA\n").unwrap();
+        });
+        fs::create_dir_all(outputs.trimmed_dir()).unwrap();
+        fs::write(outputs.trimmed_dir().join("copy.fas"), ">a\nA\n").unwrap();
+        let opts = InputOptions {
+            recursive: true,
+            skip_dirs: outputs.output_dirs(),
+            ..InputOptions::default()
+        };
+        let files = find(dir.path(), &InputFmt::Fasta, &opts);
+        assert_eq!(2, files.alignments.len());
+        let flat = find(dir.path(), &InputFmt::Fasta, &InputOptions::default());
+        assert!(flat.alignments.is_empty());
+    }
+
+    #[test]
+    fn duplicate_loci_test() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("gene_1.fas"), ">a\nA\n").unwrap();
+        fs::write(dir.path().join("gene_1.nex"), "#NEXUS\n").unwrap();
+        let dirs = [dir.path().to_string_lossy().to_string()];
+        let res = find_alignments(&dirs, &InputFmt::Auto, &InputOptions::default());
+        assert!(matches!(res, Err(MyteError::Input(_))));
+    }
+
+    #[test]
+    fn loci_filter_test() {
+        let include = [String::from("uce-*"), String::from("re:^exon_\\d+$")];
+        let exclude = [String::from("uce-2*")];
+        let filter = LociFilter::new(&include, &exclude, None).unwrap();
+        assert!(filter.is_match("uce-1"));
+        assert!(filter.is_match("exon_12"));
+        assert!(!filter.is_match("uce-20"));
+        assert!(!filter.is_match("exon_1a"));
+        assert!(LociFilter::new(&[String::from("re:(")], &[], None).is_err());
+    }
+
+    #[test]
+    fn loci_list_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("loci.txt");
+        fs::write(&list, "# loci\ngene_2.nexus\n\n").unwrap();
+        let opts = InputOptions {
+            filter: LociFilter::new(&[], &[], Some(&list)).unwrap(),
+            ..InputOptions::default()
+        };
+        let files = find(Path::new("test_files"), &InputFmt::Nexus, &opts);
+        assert_eq!(
+            vec![PathBuf::from("test_files/gene_2.nexus")],
+            files.alignments
+        );
+        assert_eq!(1, files.filtered);
+
+        fs::write(&list, "gene_3\n").unwrap();
+        let filter = LociFilter::new(&[], &[], Some(&list)).unwrap();
+        let res = filter.check_listed_loci(&files.alignments);
+        assert!(matches!(res, Err(MyteError::Input(_))));
+    }
+
+    #[test]
+    fn detect_format_test() {
+        assert_eq!(Some(InputFmt::Fasta), InputFmt::detect("\n>a\nACGT"));
+        assert_eq!(
+            Some(InputFmt::Nexus),
+            InputFmt::detect("#nexus\nbegin data;")
+        );
+        assert_eq!(Some(InputFmt::Phylip), InputFmt::detect(" 2 10\na ACGT"));
+        assert_eq!(Some(InputFmt::Phylip), InputFmt::detect("2 10 i\na ACGT"));
+        assert_eq!(None, InputFmt::detect("2 locus"));
+        assert_eq!(None, InputFmt::detect("locus\tlength"));
+    }
+}
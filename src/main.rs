@@ -1,12 +1,30 @@
 use std::panic;
+use std::process;
 use std::time::Instant;
 
+use ansi_term::Colour::{Red, White};
 use clap::crate_version;
 
+mod alignment;
+mod checkpoint;
 mod cli;
+mod concat;
+mod concordance;
+mod config;
+mod convert;
 mod deps;
+mod error;
+mod executor;
+mod filter;
+mod input;
+mod newick;
+mod rooting;
+mod shrink;
+mod summary;
 mod tree;
 mod utils;
+mod validation;
+mod writer;
 
 fn main() {
     panic::set_hook(Box::new(move |panic_info| {
@@ -15,11 +33,16 @@ fn main() {
 
     let version = crate_version!();
     let time = Instant::now();
-    cli::parse_cli(version);
+    let result = cli::parse_cli(version);
     let duration = time.elapsed();
     if duration.as_secs() < 60 {
         log::info!("Execution time: {:?}", duration);
     } else {
         utils::print_formatted_duration(duration.as_secs());
     }
+
+    if let Err(err) = result {
+        log::error!("{}: {}", White.on(Red).paint("ERROR"), err);
+        process::exit(err.exit_code());
+    }
 }
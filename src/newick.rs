@@ -0,0 +1,743 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+
+use crate::error::{MyteError, Result};
+
+// Characters that end an unquoted label or branch length
+const PUNCTUATION: &str = "()[]':;,";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    // Taxon names on leaves and support values on internal nodes.
+    // Quotes are removed.
+    pub label: Option<String>,
+    pub length: Option<f64>,
+    // The label was quoted in the input
+    quoted: bool,
+    // Branch length as written in the input.
+    // Used while it still matches the length.
+    length_text: Option<String>,
+}
+
+impl Node {
+    fn new(parent: Option<usize>) -> Self {
+        Self {
+            parent,
+            children: Vec::new(),
+            label: None,
+            length: None,
+            quoted: false,
+            length_text: None,
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    pub fn supports(&self) -> Vec<f64> {
+        self.label
+            .as_deref()
+            .map(parse_supports)
+            .unwrap_or_default()
+    }
+
+    pub fn support(&self) -> Option<f64> {
+        self.label.as_deref().and_then(parse_support)
+    }
+
+    fn write_label(&self, out: &mut String) {
+        if let Some(label) = &self.label {
+            if self.quoted || needs_quotes(label) {
+                write!(out, "'{}'", label.replace('\'', "''")).unwrap();
+            } else {
+                out.push_str(label);
+            }
+        }
+        if let Some(length) = self.length {
+            match &self.length_text {
+                Some(text) if text.parse::<f64>() == Ok(length) => {
+                    write!(out, ":{}", text).unwrap()
+                }
+                _ => write!(out, ":{}", length).unwrap(),
+            }
+        }
+    }
+}
+
+// The label and length of the branch above a node
+#[derive(Debug, Clone, PartialEq)]
+struct Branch {
+    label: Option<String>,
+    quoted: bool,
+    length: Option<f64>,
+    length_text: Option<String>,
+}
+
+// Nodes are stored in a single vector and refer to each other by index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    pub nodes: Vec<Node>,
+    pub root: usize,
+}
+
+impl Tree {
+    // Reads every tree in a file.
+    pub fn from_file(path: &Path) -> Result<Vec<Self>> {
+        let content = fs::read_to_string(path).map_err(|err| MyteError::io(path, err))?;
+        Self::parse(path, &content)
+    }
+
+    // Parses one or more trees, each ending with a semicolon.
+    pub fn parse(path: &Path, content: &str) -> Result<Vec<Self>> {
+        let mut parser = NewickParser::new(path, content);
+        let mut trees = Vec::new();
+        while parser.has_tree() {
+            trees.push(parser.parse_tree()?);
+        }
+        if trees.is_empty() {
+            return Err(MyteError::Parse {
+                path: path.to_path_buf(),
+                line: None,
+                message: String::from("Found no trees"),
+            });
+        }
+        Ok(trees)
+    }
+
+    pub fn node(&self, id: usize) -> &Node {
+        &self.nodes[id]
+    }
+
+    // Leaf ids in the order they appear in the Newick string.
+    pub fn leaves(&self) -> Vec<usize> {
+        self.preorder()
+            .into_iter()
+            .filter(|&id| self.nodes[id].is_leaf())
+            .collect()
+    }
+
+    pub fn taxa(&self) -> Vec<&str> {
+        self.leaves()
+            .into_iter()
+            .filter_map(|id| self.nodes[id].label.as_deref())
+            .collect()
+    }
+
+    // Taxa of the tips below the node
+    pub fn taxa_below(&self, id: usize) -> Vec<&str> {
+        self.preorder_from(id)
+            .into_iter()
+            .filter(|&node| self.nodes[node].is_leaf())
+            .filter_map(|node| self.nodes[node].label.as_deref())
+            .collect()
+    }
+
+    // Nodes reachable from the root, parents before children.
+    pub fn preorder(&self) -> Vec<usize> {
+        self.preorder_from(self.root)
+    }
+
+    fn preorder_from(&self, id: usize) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(self.nodes[id].children.iter().rev());
+        }
+        order
+    }
+
+    // Collapses internal branches with support below the threshold
+    // into polytomies. Branches without support values are kept.
+    // Returns the number of collapsed branches.
+    pub fn collapse_below(&mut self, threshold: f64) -> usize {
+        let collapsed: Vec<usize> = self
+            .preorder()
+            .into_iter()
+            .filter(|&id| id != self.root && !self.nodes[id].is_leaf())
+            .filter(|&id| matches!(self.nodes[id].support(), Some(support) if support < threshold))
+            .collect();
+        collapsed.iter().for_each(|&id| self.collapse(id));
+        collapsed.len()
+    }
+
+    // Removes a tip. A parent left with one child is collapsed,
+    // and a root left with one child is replaced by the child.
+    pub fn prune(&mut self, id: usize) {
+        let parent = match self.nodes[id].parent {
+            Some(parent) => parent,
+            None => return,
+        };
+        self.nodes[parent].children.retain(|&child| child != id);
+        self.nodes[id].parent = None;
+        if self.nodes[parent].children.len() != 1 {
+            return;
+        }
+        if parent != self.root {
+            self.collapse(parent);
+            return;
+        }
+        let child = self.nodes[parent].children.remove(0);
+        self.nodes[child].parent = None;
+        self.nodes[child].length = None;
+        self.root = child;
+    }
+
+    // The longest path between two tips and its end tips.
+    // Missing branch lengths count as zero.
+    pub fn diameter(&self) -> (f64, usize, usize) {
+        // Longest path from each node down to a tip
+        let mut down = vec![(0.0, self.root); self.nodes.len()];
+        let mut diameter = (0.0, self.root, self.root);
+        for id in self.preorder().into_iter().rev() {
+            let node = &self.nodes[id];
+            if node.is_leaf() {
+                down[id] = (0.0, id);
+                continue;
+            }
+            let mut paths: Vec<(f64, usize)> = node
+                .children
+                .iter()
+                .map(|&child| {
+                    let (dist, tip) = down[child];
+                    (dist + self.nodes[child].length.unwrap_or(0.0), tip)
+                })
+                .collect();
+            paths.sort_by(|a, b| b.0.total_cmp(&a.0));
+            if let [(first, a), (second, b), ..] = paths.as_slice() {
+                if first + second > diameter.0 {
+                    diameter = (first + second, *a, *b);
+                }
+            }
+            down[id] = paths[0];
+        }
+        diameter
+    }
+
+    // Leaf with the taxon name
+    pub fn find_leaf(&self, taxon: &str) -> Option<usize> {
+        self.leaves()
+            .into_iter()
+            .find(|&id| self.nodes[id].label.as_deref() == Some(taxon))
+    }
+
+    // Node whose branch separates the taxa from the other tips.
+    // None when the taxa do not form a clade in any rooting.
+    pub fn find_clade(&self, taxa: &HashSet<&str>) -> Option<usize> {
+        // Tips and matching tips below each node
+        let mut counts = vec![(0, 0); self.nodes.len()];
+        let order = self.preorder();
+        for &id in order.iter().rev() {
+            let node = &self.nodes[id];
+            counts[id] = if node.is_leaf() {
+                let found = node.label.as_deref().map_or(false, |l| taxa.contains(l));
+                (1, found as usize)
+            } else {
+                node.children.iter().fold((0, 0), |(tips, found), &child| {
+                    (tips + counts[child].0, found + counts[child].1)
+                })
+            };
+        }
+        let (tips, found) = counts[self.root];
+        if found == 0 || found == tips {
+            return None;
+        }
+        order.into_iter().filter(|&id| id != self.root).find(|&id| {
+            let (below, matched) = counts[id];
+            (matched == found && below == found) || (matched == 0 && below == tips - found)
+        })
+    }
+
+    // Roots the tree halfway along the longest path between two tips.
+    pub fn root_at_midpoint(&mut self) {
+        let (diameter, a, b) = self.diameter();
+        if a == b {
+            return;
+        }
+        let ancestors = self.ancestors(b);
+        let mut up_a = 0.0;
+        let mut node = a;
+        while !ancestors.contains(&node) {
+            up_a += self.nodes[node].length.unwrap_or(0.0);
+            node = self.nodes[node].parent.expect("Tips share the root");
+        }
+        // Walk up from the tip farther from the common ancestor
+        let half = diameter / 2.0;
+        let mut node = if up_a >= half { a } else { b };
+        let mut remaining = half;
+        while let Some(parent) = self.nodes[node].parent {
+            let length = self.nodes[node].length.unwrap_or(0.0);
+            if remaining <= length {
+                break;
+            }
+            remaining -= length;
+            node = parent;
+        }
+        self.root_on_branch(node, Some(remaining));
+    }
+
+    // Roots the tree on the branch above the node, `offset` away from it.
+    // Defaults to the middle of the branch. Branches between the new
+    // and the old root change direction, so their support labels and
+    // lengths move to the node that is now below them.
+    // An old root left with one child is removed.
+    pub fn root_on_branch(&mut self, id: usize, offset: Option<f64>) {
+        if self.nodes[id].parent.is_none() {
+            return;
+        }
+        let mut path = vec![id];
+        while let Some(parent) = self.nodes[*path.last().unwrap()].parent {
+            path.push(parent);
+        }
+        let branches: Vec<Branch> = path[..path.len() - 1]
+            .iter()
+            .map(|&node| self.branch(node))
+            .collect();
+        path.windows(2).for_each(|pair| {
+            self.nodes[pair[1]]
+                .children
+                .retain(|&child| child != pair[0]);
+        });
+        for i in 1..path.len() - 1 {
+            let (node, parent) = (path[i], path[i + 1]);
+            self.nodes[node].children.push(parent);
+            self.nodes[parent].parent = Some(node);
+            self.set_branch(parent, branches[i].clone());
+        }
+        let old_root = self.root;
+        let root = self.nodes.len();
+        let mut node = Node::new(None);
+        node.children = vec![path[0], path[1]];
+        self.nodes.push(node);
+        self.nodes[path[0]].parent = Some(root);
+        self.nodes[path[1]].parent = Some(root);
+        // Both halves split the same bipartition and keep its support.
+        // Tip labels are taxon names, not support values.
+        let mut other = branches[0].clone();
+        if self.nodes[path[0]].is_leaf() {
+            other.label = None;
+            other.quoted = false;
+        }
+        if let Some(length) = branches[0].length {
+            let offset = offset.unwrap_or(length / 2.0).clamp(0.0, length);
+            self.nodes[path[0]].length = Some(offset);
+            other.length = Some(length - offset);
+        }
+        self.set_branch(path[1], other);
+        self.root = root;
+        if self.nodes[old_root].children.len() == 1 {
+            self.collapse(old_root);
+        }
+    }
+
+    fn ancestors(&self, id: usize) -> HashSet<usize> {
+        let mut ancestors = HashSet::new();
+        let mut node = Some(id);
+        while let Some(current) = node {
+            ancestors.insert(current);
+            node = self.nodes[current].parent;
+        }
+        ancestors
+    }
+
+    fn branch(&self, id: usize) -> Branch {
+        let node = &self.nodes[id];
+        Branch {
+            label: node.label.clone(),
+            quoted: node.quoted,
+            length: node.length,
+            length_text: node.length_text.clone(),
+        }
+    }
+
+    fn set_branch(&mut self, id: usize, branch: Branch) {
+        let node = &mut self.nodes[id];
+        node.label = branch.label;
+        node.quoted = branch.quoted;
+        node.length = branch.length;
+        node.length_text = branch.length_text;
+    }
+
+    // Moves the children to the parent in place of the node.
+    // Their branches get the length of the removed branch,
+    // so root-to-tip distances stay the same.
+    fn collapse(&mut self, id: usize) {
+        let parent = match self.nodes[id].parent {
+            Some(parent) => parent,
+            None => return,
+        };
+        let children = std::mem::take(&mut self.nodes[id].children);
+        let length = self.nodes[id].length;
+        children.iter().for_each(|&child| {
+            let node = &mut self.nodes[child];
+            node.parent = Some(parent);
+            if let (Some(child_length), Some(length)) = (node.length, length) {
+                node.length = Some(child_length + length);
+            }
+        });
+        let siblings = &mut self.nodes[parent].children;
+        if let Some(pos) = siblings.iter().position(|&sibling| sibling == id) {
+            siblings.splice(pos..=pos, children);
+        }
+        self.nodes[id].parent = None;
+    }
+
+    // Walks the tree without recursion, so deep trees
+    // do not overflow the stack.
+    pub fn to_newick(&self) -> String {
+        let mut out = String::new();
+        let mut stack = vec![(self.root, 0)];
+        while let Some((id, next)) = stack.pop() {
+            let node = &self.nodes[id];
+            if next < node.children.len() {
+                out.push(if next == 0 { '(' } else { ',' });
+                stack.push((id, next + 1));
+                stack.push((node.children[next], 0));
+            } else {
+                if !node.is_leaf() {
+                    out.push(')');
+                }
+                node.write_label(&mut out);
+            }
+        }
+        out.push(';');
+        out
+    }
+}
+
+// Writes one tree per line.
+pub fn write_trees<'a, I>(path: &Path, trees: I) -> Result<()>
+where
+    I: IntoIterator<Item = &'a Tree>,
+{
+    let file = File::create(path).map_err(|err| MyteError::io(path, err))?;
+    let mut writer = BufWriter::new(file);
+    trees
+        .into_iter()
+        .try_for_each(|tree| writeln!(writer, "{}", tree.to_newick()))
+        .and_then(|_| writer.flush())
+        .map_err(|err| MyteError::io(path, err))
+}
+
+// Support values in the order IQ-TREE writes them,
+// e.g. UFBoot, SH-aLRT/UFBoot, or gCF/sCF.
+// Empty when the label is not a list of numbers.
+pub fn parse_supports(label: &str) -> Vec<f64> {
+    label
+        .split('/')
+        .map(|value| value.parse::<f64>())
+        .collect::<std::result::Result<Vec<f64>, _>>()
+        .unwrap_or_default()
+}
+
+// UFBoot when the label holds SH-aLRT/UFBoot,
+// otherwise the only support value.
+pub fn parse_support(label: &str) -> Option<f64> {
+    match parse_supports(label).as_slice() {
+        [support] | [_, support] => Some(*support),
+        _ => None,
+    }
+}
+
+fn needs_quotes(label: &str) -> bool {
+    label.is_empty()
+        || label
+            .chars()
+            .any(|c| c.is_whitespace() || PUNCTUATION.contains(c))
+}
+
+struct NewickParser<'a> {
+    path: PathBuf,
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> NewickParser<'a> {
+    fn new(path: &Path, content: &'a str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            chars: content.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn has_tree(&mut self) -> bool {
+        self.skip_whitespace().is_ok() && self.chars.peek().is_some()
+    }
+
+    // Opening a bracket adds a child and moves into it.
+    // Commas add a sibling, and closing brackets move back to the parent.
+    fn parse_tree(&mut self) -> Result<Tree> {
+        let mut nodes = vec![Node::new(None)];
+        let mut current = 0;
+        loop {
+            self.skip_whitespace()?;
+            let c = match self.chars.peek() {
+                Some(&c) => c,
+                None => return Err(self.error("Missing semicolon at the end of the tree")),
+            };
+            match c {
+                '(' | ',' => {
+                    self.chars.next();
+                    let parent = if c == '(' {
+                        let node = &nodes[current];
+                        if !node.is_leaf() || node.label.is_some() || node.length.is_some() {
+                            return Err(self.error("Unexpected opening bracket"));
+                        }
+                        current
+                    } else {
+                        match nodes[current].parent {
+                            Some(parent) => parent,
+                            None => return Err(self.error("Unexpected comma outside brackets")),
+                        }
+                    };
+                    current = nodes.len();
+                    nodes.push(Node::new(Some(parent)));
+                    nodes[parent].children.push(current);
+                }
+                ')' => {
+                    self.chars.next();
+                    current = match nodes[current].parent {
+                        Some(parent) => parent,
+                        None => return Err(self.error("Unmatched closing bracket")),
+                    };
+                }
+                ':' => {
+                    self.chars.next();
+                    if nodes[current].length.is_some() {
+                        return Err(self.error("Found more than one branch length"));
+                    }
+                    self.skip_whitespace()?;
+                    let text = self.read_unquoted();
+                    let length = text
+                        .parse::<f64>()
+                        .map_err(|_| self.error(&format!("Invalid branch length '{}'", text)))?;
+                    nodes[current].length = Some(length);
+                    nodes[current].length_text = Some(text);
+                }
+                ';' => {
+                    self.chars.next();
+                    if current != 0 {
+                        return Err(self.error("Unmatched opening bracket"));
+                    }
+                    return Ok(Tree {
+                        nodes,
+                        root: current,
+                    });
+                }
+                _ => {
+                    let node = &nodes[current];
+                    if node.label.is_some() || node.length.is_some() {
+                        return Err(self.error("Unexpected label"));
+                    }
+                    let (label, quoted) = self.read_label()?;
+                    if label.is_empty() && !quoted {
+                        return Err(self.error(&format!("Unexpected character '{}'", c)));
+                    }
+                    nodes[current].label = Some(label);
+                    nodes[current].quoted = quoted;
+                }
+            }
+        }
+    }
+
+    // Skips whitespace and bracketed comments.
+    fn skip_whitespace(&mut self) -> Result<()> {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '[' => {
+                    let line = self.line;
+                    self.chars.next();
+                    loop {
+                        match self.next_char() {
+                            Some(']') => break,
+                            Some(_) => (),
+                            None => return Err(self.error_at(line, "Unclosed comment")),
+                        }
+                    }
+                }
+                c if c.is_whitespace() => {
+                    self.next_char();
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    // Quoted labels escape quotes by doubling them.
+    fn read_label(&mut self) -> Result<(String, bool)> {
+        if self.chars.peek() != Some(&'\'') {
+            return Ok((self.read_unquoted(), false));
+        }
+        let line = self.line;
+        self.chars.next();
+        let mut label = String::new();
+        loop {
+            match self.next_char() {
+                Some('\'') if self.chars.peek() == Some(&'\'') => {
+                    self.chars.next();
+                    label.push('\'');
+                }
+                Some('\'') => return Ok((label, true)),
+                Some(c) => label.push(c),
+                None => return Err(self.error_at(line, "Unclosed quoted label")),
+            }
+        }
+    }
+
+    fn read_unquoted(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || PUNCTUATION.contains(c) {
+                break;
+            }
+            text.push(c);
+            self.chars.next();
+        }
+        text
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn error(&self, message: &str) -> MyteError {
+        self.error_at(self.line, message)
+    }
+
+    fn error_at(&self, line: usize, message: &str) -> MyteError {
+        MyteError::Parse {
+            path: self.path.clone(),
+            line: Some(line),
+            message: String::from(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(content: &str) -> Result<Vec<Tree>> {
+        Tree::parse(Path::new("genes.treefile"), content)
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let trees = [
+            "(A:0.1,B:0.2,(C:0.3,D:0.4)100:0.5);",
+            "(A:0.0000010000,(B:1e-06,C:0.10)'85.2/97':0.05,D);",
+            "((A,B)'100/45.5/38.2',('taxon c','it''s d'),E)root;",
+            "(,(,));",
+        ];
+        trees.iter().for_each(|newick| {
+            let tree = parse(newick).unwrap();
+            assert_eq!(*newick, tree[0].to_newick());
+        });
+    }
+
+    #[test]
+    fn parse_tree_test() {
+        let content = "[comment]\n(A:0.1,'B c':0.2,\n(C,D)80/95:0.5);\n(A,B);\n";
+        let trees = parse(content).unwrap();
+        assert_eq!(2, trees.len());
+        let tree = &trees[0];
+        assert_eq!(vec!["A", "B c", "C", "D"], tree.taxa());
+        let clade = tree.node(tree.root).children[2];
+        assert_eq!(vec![80.0, 95.0], tree.node(clade).supports());
+        assert_eq!(Some(0.5), tree.node(clade).length);
+        assert_eq!(Some(tree.root), tree.node(clade).parent);
+        assert!(tree.node(tree.leaves()[0]).supports().is_empty());
+    }
+
+    #[test]
+    fn edited_length_test() {
+        let mut trees = parse("(A:0.10,B:0.2);").unwrap();
+        trees[0].nodes[1].length = Some(0.5);
+        assert_eq!("(A:0.5,B:0.2);", trees[0].to_newick());
+    }
+
+    #[test]
+    fn collapse_test() {
+        let content = "((A:1,B:1)30/95:1,((C:1,D:1)80/40:0.5,E:1)50:1,(F,G)bad);";
+        let mut tree = parse(content).unwrap().remove(0);
+        assert_eq!(2, tree.collapse_below(60.0));
+        assert_eq!(
+            "((A:1,B:1)30/95:1,C:2.5,D:2.5,E:2,(F,G)bad);",
+            tree.to_newick()
+        );
+        assert_eq!(7, tree.taxa().len());
+    }
+
+    #[test]
+    fn prune_test() {
+        let mut tree = parse("((A:1,B:2)90:1,C:1,(D:1,E:5):2);").unwrap().remove(0);
+        assert_eq!((10.0, 7, 3), tree.diameter());
+        tree.prune(7);
+        assert_eq!("((A:1,B:2)90:1,C:1,D:3);", tree.to_newick());
+        assert_eq!((6.0, 3, 6), tree.diameter());
+        tree.prune(2);
+        tree.prune(3);
+        assert_eq!("(C:1,D:3);", tree.to_newick());
+    }
+
+    #[test]
+    fn root_on_branch_test() {
+        let content = "(A:1,B:1,((C:1,D:1)90:2,E:1)80:1);";
+        let mut tree = parse(content).unwrap().remove(0);
+        let clade = tree
+            .find_leaf("C")
+            .and_then(|id| tree.node(id).parent)
+            .unwrap();
+        tree.root_on_branch(clade, None);
+        assert_eq!("((C:1,D:1)90:1,(E:1,(A:1,B:1)80:1)90:1);", tree.to_newick());
+        let outgroup: HashSet<&str> = ["A", "B"].iter().copied().collect();
+        let clade = tree.find_clade(&outgroup).unwrap();
+        tree.root_on_branch(clade, None);
+        assert_eq!(
+            "((A:1,B:1)80:0.5,(E:1,(C:1,D:1)90:2)80:0.5);",
+            tree.to_newick()
+        );
+        let split: HashSet<&str> = ["A", "C"].iter().copied().collect();
+        assert_eq!(None, tree.find_clade(&split));
+    }
+
+    #[test]
+    fn midpoint_test() {
+        let mut tree = parse("(A:1,B:1,(C:1,D:7)50:1);").unwrap().remove(0);
+        tree.root_at_midpoint();
+        assert_eq!("(D:4.5,(C:1,(A:1,B:1)50:1):2.5);", tree.to_newick());
+    }
+
+    #[test]
+    fn parse_error_test() {
+        let errors = [
+            ("(A,B)", 1),
+            ("(A,B));", 1),
+            ("(A,\n(B,C);", 2),
+            ("(A:x,B);", 1),
+            ("(A,'B);", 1),
+            ("A,B;", 1),
+            ("(A,B)(C,D);", 1),
+        ];
+        errors.iter().for_each(|(content, line)| {
+            let res = parse(content);
+            assert!(matches!(res, Err(MyteError::Parse { line: Some(l), .. }) if l == *line));
+        });
+        assert!(matches!(
+            parse("  \n"),
+            Err(MyteError::Parse { line: None, .. })
+        ));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{MyteError, Result};
+use crate::newick::{self, Tree};
+use crate::tree::OutputDir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RootMethod {
+    Outgroup,
+    // Rooted on the outgroup taxa present in the tree
+    Fallback,
+    Midpoint,
+}
+
+// Writes rooted copies of the trees written by the run to the rooted
+// tree directory. The directory is cleared first, so rooted trees
+// left from earlier runs are not kept. The original trees are left
+// unrooted for IQ-TREE and ASTRAL. Returns the rooted tree files.
+pub fn root_trees(
+    trees: &[PathBuf],
+    outgroup: &[String],
+    outputs: &OutputDir,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let dir = outputs.rooted_dir();
+    log::info!("{:18}: {}", "Outgroup", outgroup.join(", "));
+    if dry_run {
+        log::info!("{:18}: {}\n", "Rooted dir", dir.display());
+        return Ok(Vec::new());
+    }
+    let paths: Vec<&PathBuf> = trees.iter().filter(|path| path.is_file()).collect();
+    if dir.is_dir() {
+        fs::remove_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    }
+    fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    let mut rooted = Vec::with_capacity(paths.len());
+    for path in paths {
+        let output = dir.join(path.file_name().expect("Tree files have names"));
+        let methods = root_tree_file(path, &output, outgroup)?;
+        print_rooting_info(&output, &methods);
+        rooted.push(output);
+    }
+    log::info!("{:18}: {}\n", "Rooted dir", dir.display());
+    Ok(rooted)
+}
+
+fn root_tree_file(path: &Path, output: &Path, outgroup: &[String]) -> Result<Vec<RootMethod>> {
+    let mut trees = Tree::from_file(path)?;
+    let methods = trees
+        .iter_mut()
+        .map(|tree| root_tree(tree, outgroup))
+        .collect();
+    newick::write_trees(output, &trees)?;
+    Ok(methods)
+}
+
+// Outgroup taxa are listed from the closest to the ingroup.
+// Trees missing some of them are rooted on the taxa present when
+// those form a clade, otherwise on the closest taxon present.
+// Trees without any are rooted at the midpoint.
+fn root_tree(tree: &mut Tree, outgroup: &[String]) -> RootMethod {
+    let present: Vec<&str> = outgroup
+        .iter()
+        .map(|taxon| taxon.as_str())
+        .filter(|taxon| tree.find_leaf(taxon).is_some())
+        .collect();
+    let closest = match present.first() {
+        Some(taxon) => *taxon,
+        None => {
+            tree.root_at_midpoint();
+            return RootMethod::Midpoint;
+        }
+    };
+    let taxa: HashSet<&str> = present.iter().copied().collect();
+    match tree.find_clade(&taxa) {
+        Some(clade) => {
+            tree.root_on_branch(clade, None);
+            if present.len() == outgroup.len() {
+                RootMethod::Outgroup
+            } else {
+                RootMethod::Fallback
+            }
+        }
+        None => {
+            let tip = tree
+                .find_leaf(closest)
+                .expect("Outgroup taxon is in the tree");
+            tree.root_on_branch(tip, None);
+            RootMethod::Fallback
+        }
+    }
+}
+
+fn print_rooting_info(output: &Path, methods: &[RootMethod]) {
+    let count = |method: RootMethod| methods.iter().filter(|&&m| m == method).count();
+    log::info!(
+        "{:18}: {} (outgroup: {}, fallback: {}, midpoint: {})",
+        "Rooted",
+        output.display(),
+        count(RootMethod::Outgroup),
+        count(RootMethod::Fallback),
+        count(RootMethod::Midpoint)
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(content: &str) -> Tree {
+        Tree::parse(Path::new("genes.treefiles"), content)
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn root_tree_test() {
+        let outgroup = vec![String::from("E"), String::from("F")];
+        let mut tree = parse("(A:1,B:1,((C:1,F:1)90:1,E:1)80:1);");
+        assert_eq!(RootMethod::Fallback, root_tree(&mut tree, &outgroup));
+        assert_eq!(
+            "(E:0.5,((C:1,F:1)90:1,(A:1,B:1)80:1):0.5);",
+            tree.to_newick()
+        );
+        let mut tree = parse("(A:1,B:1,(C:1,F:1)90:1);");
+        assert_eq!(RootMethod::Fallback, root_tree(&mut tree, &outgroup));
+        let mut tree = parse("(A:1,B:1,(C:1,D:1)90:1);");
+        assert_eq!(RootMethod::Midpoint, root_tree(&mut tree, &outgroup));
+        let mut tree = parse("(A:1,(E:1,F:1)100:1,(C:1,D:1)90:1);");
+        assert_eq!(RootMethod::Outgroup, root_tree(&mut tree, &outgroup));
+        assert_eq!(
+            "((E:1,F:1)100:0.5,(A:1,(C:1,D:1)90:1)100:0.5);",
+            tree.to_newick()
+        );
+    }
+
+    #[test]
+    fn root_trees_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        fs::write(
+            outputs.gene_trees_file(),
+            "(A:1,B:1,(C:1,D:1)90:1);\n(A:1,B:1,(C:1,E:5)90:1);\n",
+        )
+        .unwrap();
+        // Left from an earlier run
+        fs::write(outputs.msc_tree_file(), "(A:1,B:1,(C:1,D:1):1);\n").unwrap();
+        fs::create_dir_all(outputs.rooted_dir()).unwrap();
+        let stale = outputs.rooted_dir().join("msc_astral.tree");
+        fs::write(&stale, "(D:1,(C:1,(A:1,B:1):1):1);\n").unwrap();
+        let outgroup = vec![String::from("D")];
+        let trees = vec![outputs.gene_trees_file(), outputs.species_tree_file()];
+        let rooted = root_trees(&trees, &outgroup, &outputs, false).unwrap();
+        assert_eq!(vec![outputs.rooted_dir().join("genes.treefiles")], rooted);
+        assert!(!stale.exists());
+        let trees = fs::read_to_string(&rooted[0]).unwrap();
+        let lines: Vec<&str> = trees.lines().collect();
+        assert_eq!("(D:0.5,(C:1,(A:1,B:1)90:1):0.5);", lines[0]);
+        assert_eq!("(E:3.5,(C:1,(A:1,B:1)90:1):1.5);", lines[1]);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::alignment::Alignment;
+use crate::error::{MyteError, Result};
+use crate::filter;
+use crate::input::InputFmt;
+use crate::newick::{self, Tree};
+use crate::tree::{self, OutputDir};
+use crate::utils;
+use crate::writer;
+
+// Gene trees keep at least this many tips
+const MIN_TIPS: usize = 4;
+
+// Long-branch outlier removal in the spirit of TreeShrink.
+// Tips are removed one at a time while removing the tip
+// shrinks the tree diameter by more than the ratio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShrinkSettings {
+    pub ratio: f64,
+    // Collapsed after pruning, so ASTRAL gets both
+    pub collapse_below: Option<f64>,
+    pub prune_alignments: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RemovedTip {
+    taxon: String,
+    // Diameter before removing the tip over the diameter after
+    ratio: f64,
+}
+
+struct ShrunkLocus {
+    locus: String,
+    trees: Vec<Tree>,
+    removed: Vec<RemovedTip>,
+}
+
+// Reads the gene trees of the alignments in the gene tree directory.
+// Returns the shrunk gene tree file.
+pub fn shrink_gene_trees(
+    settings: &ShrinkSettings,
+    alignments: &[PathBuf],
+    input_fmt: &InputFmt,
+    outputs: &OutputDir,
+    dry_run: bool,
+) -> Result<PathBuf> {
+    let gene_trees = outputs.shrunk_gene_trees_file();
+    let report = outputs.shrunk_taxa_file();
+    if dry_run {
+        print_shrink_plan(settings, &gene_trees, outputs);
+        return Ok(gene_trees);
+    }
+    let loci = get_tree_files(alignments, &outputs.gene_tree_dir())?
+        .par_iter()
+        .map(|path| shrink_locus(path, settings))
+        .collect::<Result<Vec<ShrunkLocus>>>()?;
+    let trees = loci.iter().flat_map(|locus| locus.trees.iter());
+    newick::write_trees(&gene_trees, trees)?;
+    write_shrink_report(&report, &loci)?;
+    print_shrink_info(&loci, &gene_trees, &report);
+    if settings.prune_alignments {
+        prune_alignments(alignments, input_fmt, &loci, outputs)?;
+    }
+    Ok(gene_trees)
+}
+
+fn get_tree_files(alignments: &[PathBuf], dir: &Path) -> Result<Vec<PathBuf>> {
+    let files = tree::get_gene_tree_files(alignments, dir);
+    if files.is_empty() {
+        return Err(MyteError::Input(format!(
+            "Found no gene trees to shrink in {}",
+            dir.display()
+        )));
+    }
+    Ok(files)
+}
+
+fn shrink_locus(path: &Path, settings: &ShrinkSettings) -> Result<ShrunkLocus> {
+    let mut trees = Tree::from_file(path)?;
+    let mut removed = Vec::new();
+    trees.iter_mut().for_each(|tree| {
+        removed.extend(shrink_tree(tree, settings.ratio));
+        if let Some(threshold) = settings.collapse_below {
+            tree.collapse_below(threshold);
+        }
+    });
+    Ok(ShrunkLocus {
+        locus: tree::get_locus_name(path).to_string(),
+        trees,
+        removed,
+    })
+}
+
+// Only the end tips of the longest path change the diameter,
+// so they are the only candidates at each step.
+fn shrink_tree(tree: &mut Tree, ratio: f64) -> Vec<RemovedTip> {
+    let mut removed = Vec::new();
+    if tree.leaves().len() <= MIN_TIPS {
+        return removed;
+    }
+    let max_removed = get_max_removed(tree.leaves().len());
+    while removed.len() < max_removed && tree.leaves().len() > MIN_TIPS {
+        let (diameter, a, b) = tree.diameter();
+        if diameter <= 0.0 {
+            break;
+        }
+        let prune = |tip: usize| {
+            let mut pruned = tree.clone();
+            pruned.prune(tip);
+            let (shrunk, _, _) = pruned.diameter();
+            (tip, pruned, shrunk)
+        };
+        let (first, second) = (prune(a), prune(b));
+        let (tip, pruned, shrunk) = if second.2 < first.2 { second } else { first };
+        let tip_ratio = diameter / shrunk;
+        if tip_ratio <= ratio {
+            break;
+        }
+        removed.push(RemovedTip {
+            taxon: tree.node(tip).label.clone().unwrap_or_default(),
+            ratio: tip_ratio,
+        });
+        *tree = pruned;
+    }
+    removed
+}
+
+// TreeShrink removes at most min(n/4, 5 * sqrt(n)) tips per tree.
+fn get_max_removed(tips: usize) -> usize {
+    let limit = 5.0 * (tips as f64).sqrt();
+    (tips / 4).min(limit as usize)
+}
+
+fn write_shrink_report(report: &Path, loci: &[ShrunkLocus]) -> Result<()> {
+    let rows: Vec<String> = loci
+        .iter()
+        .flat_map(|locus| {
+            locus.removed.iter().map(move |tip| {
+                let ratio = if tip.ratio.is_finite() {
+                    format!("{:.4}", tip.ratio)
+                } else {
+                    String::from("inf")
+                };
+                format!("{}\t{}\t{}", locus.locus, tip.taxon, ratio)
+            })
+        })
+        .collect();
+    utils::write_report_or_remove(report, &rows, "locus\ttaxon\tdiameter_ratio")
+}
+
+// Every alignment is written, so the directory holds the whole dataset.
+fn prune_alignments(
+    paths: &[PathBuf],
+    input_fmt: &InputFmt,
+    loci: &[ShrunkLocus],
+    outputs: &OutputDir,
+) -> Result<()> {
+    let removed: HashMap<&str, HashSet<&str>> = loci
+        .iter()
+        .map(|locus| {
+            let taxa = locus.removed.iter().map(|tip| tip.taxon.as_str());
+            (locus.locus.as_str(), taxa.collect())
+        })
+        .collect();
+    let dir = outputs.shrunk_dir();
+    if dir.is_dir() {
+        fs::remove_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    }
+    fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    let output_fmt = filter::get_output_fmt(input_fmt);
+    let no_taxa = HashSet::new();
+    paths.par_iter().try_for_each(|path| {
+        let locus = tree::get_locus_name(path);
+        let aln = Alignment::from_file(path, input_fmt)?;
+        let taxa = removed.get(locus.as_ref()).unwrap_or(&no_taxa);
+        let aln = filter::remove_taxa(aln, taxa);
+        let output = writer::get_output_path(&dir, &locus, &output_fmt);
+        writer::write_alignment(&aln, &output, &output_fmt)
+    })?;
+    log::info!(
+        "{:18}: {} alignments in {}\n",
+        "Shrunk dir",
+        paths.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+fn print_shrink_plan(settings: &ShrinkSettings, gene_trees: &Path, outputs: &OutputDir) {
+    log::info!("{:18}: {}", "Diameter ratio", settings.ratio);
+    log::info!("{:18}: {}", "Shrunk trees", gene_trees.display());
+    if settings.prune_alignments {
+        log::info!("{:18}: {}", "Shrunk dir", outputs.shrunk_dir().display());
+    }
+    log::info!("");
+}
+
+fn print_shrink_info(loci: &[ShrunkLocus], gene_trees: &Path, report: &Path) {
+    let shrunk = loci
+        .iter()
+        .filter(|locus| !locus.removed.is_empty())
+        .count();
+    let removed: usize = loci.iter().map(|locus| locus.removed.len()).sum();
+    log::info!("{:18}: {}", "Gene trees", loci.len());
+    log::info!("{:18}: {} tips in {} loci", "Removed", removed, shrunk);
+    if removed > 0 {
+        log::info!("{:18}: {}", "Report", report.display());
+    }
+    log::info!("{:18}: {}\n", "Shrunk trees", gene_trees.display());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shrink_tree_test() {
+        let content = "(A:0.1,B:0.1,(C:0.1,D:0.1):0.1,(E:0.1,(F:0.1,G:0.1):0.1):0.1,H:2.0);";
+        let mut tree = Tree::parse(Path::new("gene_1.treefile"), content)
+            .unwrap()
+            .remove(0);
+        let removed = shrink_tree(&mut tree, 2.0);
+        assert_eq!(1, removed.len());
+        assert_eq!("H", removed[0].taxon);
+        assert_eq!(7, tree.leaves().len());
+        assert!(shrink_tree(&mut tree, 2.0).is_empty());
+        assert_eq!(0, get_max_removed(3));
+        let mut small = Tree::parse(
+            Path::new("gene_2.treefile"),
+            "(A:0.1,B:0.1,(C:0.1,D:9.0):0.1);",
+        )
+        .unwrap()
+        .remove(0);
+        assert!(shrink_tree(&mut small, 2.0).is_empty());
+    }
+
+    #[test]
+    fn shrink_gene_trees_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let treedir = outputs.gene_tree_dir();
+        fs::create_dir_all(&treedir).unwrap();
+        fs::write(
+            treedir.join("gene_1.treefile"),
+            "((A:0.1,B:0.1)100:0.1,(C:0.1,D:0.1)40:0.1,(E:0.1,F:0.1):0.1,G:0.1,H:3.0);\n",
+        )
+        .unwrap();
+        // Left from a locus filtered out since an earlier run
+        fs::write(treedir.join("gene_2.treefile"), "(A:0.1,B:0.1,C:9.0);\n").unwrap();
+        let aln = dir.path().join("gene_1.fas");
+        fs::write(&aln, ">A\nAC\n>G\nAC\n>H\nAC\n").unwrap();
+        let settings = ShrinkSettings {
+            ratio: 2.0,
+            collapse_below: Some(50.0),
+            prune_alignments: true,
+        };
+        let gene_trees =
+            shrink_gene_trees(&settings, &[aln], &InputFmt::Fasta, &outputs, false).unwrap();
+        let trees = fs::read_to_string(gene_trees).unwrap();
+        assert_eq!(
+            "((A:0.1,B:0.1)100:0.1,C:0.2,D:0.2,(E:0.1,F:0.1):0.1,G:0.1);\n",
+            trees
+        );
+        let report = fs::read_to_string(outputs.shrunk_taxa_file()).unwrap();
+        assert!(report.lines().nth(1).unwrap().starts_with("gene_1\tH\t"));
+        let pruned = outputs.shrunk_dir().join("gene_1.fas");
+        let pruned = Alignment::from_file(&pruned, &InputFmt::Fasta).unwrap();
+        assert_eq!(vec!["A", "G"], pruned.taxa);
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+
+use crate::alignment::{Alignment, DataType};
+use crate::error::{MyteError, Result};
+use crate::input::InputFmt;
+use crate::tree::{self, OutputDir};
+
+// Unambiguous states counted for variable and informative sites
+const DNA_STATES: &str = "ACGTU";
+const PROTEIN_STATES: &str = "ACDEFGHIKLMNPQRSTVWY";
+const STANDARD_STATES: &str = "0123456789";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocusSummary {
+    pub path: PathBuf,
+    pub locus: String,
+    pub taxa: Vec<String>,
+    pub nchar: usize,
+    // Gaps and missing data across all cells
+    pub missing: usize,
+    // GC and ACGT counts. Only for nucleotides.
+    pub gc: Option<(usize, usize)>,
+    pub variable_sites: usize,
+    pub informative_sites: usize,
+}
+
+impl LocusSummary {
+    fn new(aln: &Alignment) -> Self {
+        let (variable_sites, informative_sites) = count_sites(aln);
+        Self {
+            path: aln.path.clone(),
+            locus: tree::get_locus_name(&aln.path).to_string(),
+            taxa: aln.taxa.clone(),
+            nchar: aln.nchar(),
+            missing: aln
+                .sequences
+                .iter()
+                .flat_map(|seq| seq.chars())
+                .filter(|&c| aln.is_missing(c))
+                .count(),
+            gc: count_gc(aln),
+            variable_sites,
+            informative_sites,
+        }
+    }
+
+    fn cells(&self) -> usize {
+        self.taxa.len() * self.nchar
+    }
+
+    pub fn missing_ratio(&self) -> f64 {
+        if self.cells() == 0 {
+            return 0.0;
+        }
+        self.missing as f64 / self.cells() as f64
+    }
+}
+
+// Summary files written to the summary directory
+pub struct SummaryFiles {
+    pub loci: PathBuf,
+    pub overall: PathBuf,
+    pub occupancy: PathBuf,
+}
+
+impl SummaryFiles {
+    fn new(dir: &Path) -> Self {
+        Self {
+            loci: dir.join("locus_summary.csv"),
+            overall: dir.join("overall_summary.csv"),
+            occupancy: dir.join("taxon_occupancy.csv"),
+        }
+    }
+}
+
+pub fn summarize_alignments(
+    paths: &[PathBuf],
+    input_fmt: &InputFmt,
+    outputs: &OutputDir,
+) -> Result<SummaryFiles> {
+    if paths.is_empty() {
+        return Err(MyteError::Input(String::from(
+            "Found no alignments to summarize",
+        )));
+    }
+    let summaries = summarize_loci(paths, input_fmt)?;
+    let dir = outputs.summary_dir();
+    fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+    let files = SummaryFiles::new(&dir);
+    write_locus_summary(&files.loci, &summaries).map_err(|err| MyteError::io(&files.loci, err))?;
+    write_overall_summary(&files.overall, &summaries)
+        .map_err(|err| MyteError::io(&files.overall, err))?;
+    write_occupancy(&files.occupancy, &summaries)
+        .map_err(|err| MyteError::io(&files.occupancy, err))?;
+    print_summary_info(&summaries, &files);
+    Ok(files)
+}
+
+// Loci are sorted, so the tables are in the same order on every run.
+pub fn summarize_loci(paths: &[PathBuf], input_fmt: &InputFmt) -> Result<Vec<LocusSummary>> {
+    let summaries = Mutex::new(Vec::with_capacity(paths.len()));
+    paths.par_iter().try_for_each(|path| {
+        let aln = Alignment::from_file(path, input_fmt)?;
+        summaries.lock().unwrap().push(LocusSummary::new(&aln));
+        Ok(())
+    })?;
+    let mut summaries = summaries.into_inner().unwrap();
+    summaries.sort_by(|a, b| a.locus.cmp(&b.locus));
+    Ok(summaries)
+}
+
+fn count_gc(aln: &Alignment) -> Option<(usize, usize)> {
+    if !matches!(aln.datatype, DataType::Dna | DataType::Rna) {
+        return None;
+    }
+    let mut gc = 0;
+    let mut acgt = 0;
+    aln.sequences
+        .iter()
+        .flat_map(|seq| seq.chars())
+        .map(|c| c.to_ascii_uppercase())
+        .for_each(|c| match c {
+            'G' | 'C' => {
+                gc += 1;
+                acgt += 1;
+            }
+            'A' | 'T' | 'U' => acgt += 1,
+            _ => (),
+        });
+    Some((gc, acgt))
+}
+
+// A site is parsimony informative when at least two states
+// occur in at least two taxa each. Ambiguous states are ignored.
+fn count_sites(aln: &Alignment) -> (usize, usize) {
+    let states = match aln.datatype {
+        DataType::Dna | DataType::Rna => DNA_STATES,
+        DataType::Protein => PROTEIN_STATES,
+        DataType::Standard => STANDARD_STATES,
+    };
+    let sequences: Vec<&[u8]> = aln.sequences.iter().map(|seq| seq.as_bytes()).collect();
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    let mut variable = 0;
+    let mut informative = 0;
+    for i in 0..aln.nchar() {
+        counts.clear();
+        sequences
+            .iter()
+            .map(|seq| seq[i].to_ascii_uppercase())
+            .filter(|&c| states.as_bytes().contains(&c))
+            .for_each(|c| *counts.entry(c).or_insert(0) += 1);
+        if counts.len() > 1 {
+            variable += 1;
+            if counts.values().filter(|&&count| count > 1).count() > 1 {
+                informative += 1;
+            }
+        }
+    }
+    (variable, informative)
+}
+
+fn write_locus_summary(path: &Path, summaries: &[LocusSummary]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "locus,taxa,length,missing,gc,variable_sites,informative_sites"
+    )?;
+    summaries.iter().try_for_each(|locus| {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{}",
+            csv_field(&locus.locus),
+            locus.taxa.len(),
+            locus.nchar,
+            format_ratio(locus.missing, locus.cells()),
+            format_gc(locus.gc),
+            locus.variable_sites,
+            locus.informative_sites
+        )
+    })?;
+    writer.flush()
+}
+
+fn write_overall_summary(path: &Path, summaries: &[LocusSummary]) -> std::io::Result<()> {
+    let taxa = get_all_taxa(summaries);
+    let sites: usize = summaries.iter().map(|locus| locus.nchar).sum();
+    let cells: usize = summaries.iter().map(|locus| locus.cells()).sum();
+    let missing: usize = summaries.iter().map(|locus| locus.missing).sum();
+    let gc = summaries.iter().filter_map(|locus| locus.gc).fold(
+        None,
+        |total: Option<(usize, usize)>, (gc, acgt)| {
+            let (total_gc, total_acgt) = total.unwrap_or((0, 0));
+            Some((total_gc + gc, total_acgt + acgt))
+        },
+    );
+    let variable: usize = summaries.iter().map(|locus| locus.variable_sites).sum();
+    let informative: usize = summaries.iter().map(|locus| locus.informative_sites).sum();
+    let mean_taxa = mean(summaries.iter().map(|locus| locus.taxa.len()));
+    let mean_length = mean(summaries.iter().map(|locus| locus.nchar));
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "loci,taxa,sites,missing,gc,variable_sites,informative_sites,mean_taxa,mean_length"
+    )?;
+    writeln!(
+        writer,
+        "{},{},{},{},{},{},{},{:.2},{:.2}",
+        summaries.len(),
+        taxa.len(),
+        sites,
+        format_ratio(missing, cells),
+        format_gc(gc),
+        variable,
+        informative,
+        mean_taxa,
+        mean_length
+    )?;
+    writer.flush()
+}
+
+// Rows are taxa and columns are loci. 1 marks a taxon present in a locus.
+fn write_occupancy(path: &Path, summaries: &[LocusSummary]) -> std::io::Result<()> {
+    let taxa = get_all_taxa(summaries);
+    let mut writer = BufWriter::new(File::create(path)?);
+    let loci: Vec<String> = summaries
+        .iter()
+        .map(|locus| csv_field(&locus.locus))
+        .collect();
+    writeln!(writer, "taxon,{},loci", loci.join(","))?;
+    taxa.iter().try_for_each(|taxon| {
+        let presence: Vec<bool> = summaries
+            .iter()
+            .map(|locus| locus.taxa.contains(taxon))
+            .collect();
+        let cells: Vec<&str> = presence
+            .iter()
+            .map(|&present| if present { "1" } else { "0" })
+            .collect();
+        let count = presence.iter().filter(|&&present| present).count();
+        writeln!(writer, "{},{},{}", csv_field(taxon), cells.join(","), count)
+    })?;
+    writer.flush()
+}
+
+fn get_all_taxa(summaries: &[LocusSummary]) -> BTreeSet<String> {
+    summaries
+        .iter()
+        .flat_map(|locus| locus.taxa.iter().cloned())
+        .collect()
+}
+
+fn mean<I: Iterator<Item = usize>>(values: I) -> f64 {
+    let (sum, count) = values.fold((0, 0), |(sum, count), value| (sum + value, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum as f64 / count as f64
+    }
+}
+
+fn format_ratio(count: usize, total: usize) -> String {
+    if total == 0 {
+        return String::from("NA");
+    }
+    format!("{:.4}", count as f64 / total as f64)
+}
+
+fn format_gc(gc: Option<(usize, usize)>) -> String {
+    match gc {
+        Some((gc, acgt)) => format_ratio(gc, acgt),
+        None => String::from("NA"),
+    }
+}
+
+// Quotes names that contain commas or quotes.
+pub fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
+
+fn print_summary_info(summaries: &[LocusSummary], files: &SummaryFiles) {
+    log::info!("{:18}: {}", "Loci", summaries.len());
+    log::info!("{:18}: {}", "Taxa", get_all_taxa(summaries).len());
+    log::info!("{:18}: {}", "Locus summary", files.loci.display());
+    log::info!("{:18}: {}", "Overall summary", files.overall.display());
+    log::info!("{:18}: {}\n", "Taxon occupancy", files.occupancy.display());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locus_summary_test() {
+        let content = ">a\nACGTA-\n>b\nACGTTN\n>c\nATGCT?\n>d\nGTGCAA\n";
+        let aln = Alignment::parse(Path::new("gene_1.fas"), content, &InputFmt::Fasta).unwrap();
+        let summary = LocusSummary::new(&aln);
+        assert_eq!("gene_1", summary.locus);
+        assert_eq!(3, summary.missing);
+        assert_eq!(Some((9, 21)), summary.gc);
+        assert_eq!(4, summary.variable_sites);
+        assert_eq!(3, summary.informative_sites);
+    }
+
+    #[test]
+    fn summary_files_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let gene_1 = dir.path().join("gene_1.fas");
+        fs::write(&gene_1, ">a\nACGT\n>b\nAC-T\n").unwrap();
+        let gene_2 = dir.path().join("gene_2.fas");
+        fs::write(&gene_2, ">c,1\nGG\n>a\nGC\n").unwrap();
+        let outputs = OutputDir::new(dir.path().join("results"));
+        let paths = vec![gene_2, gene_1];
+        let files = summarize_alignments(&paths, &InputFmt::Fasta, &outputs).unwrap();
+        let loci = fs::read_to_string(&files.loci).unwrap();
+        let lines: Vec<&str> = loci.lines().collect();
+        assert_eq!("gene_1,2,4,0.1250,0.4286,0,0", lines[1]);
+        let overall = fs::read_to_string(&files.overall).unwrap();
+        assert!(overall.lines().nth(1).unwrap().starts_with("2,3,6,"));
+        let occupancy = fs::read_to_string(&files.occupancy).unwrap();
+        let lines: Vec<&str> = occupancy.lines().collect();
+        assert_eq!("taxon,gene_1,gene_2,loci", lines[0]);
+        assert_eq!("a,1,1,2", lines[1]);
+        assert_eq!("\"c,1\",0,1,1", lines[3]);
+    }
+}
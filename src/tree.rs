@@ -1,19 +1,25 @@
 use std::fs;
 use std::fs::File;
-use std::io::{BufWriter, Read, Result, Write};
-use std::panic;
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
-use std::str;
+use std::sync::Mutex;
 
 use ansi_term::Colour::{Red, White};
-use glob::glob;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 
-// Executable file name
+use crate::checkpoint::{self, Fingerprint};
+use crate::concat::ConcatFiles;
+use crate::error::{MyteError, Result};
+use crate::executor::{Executor, ToolCommand, ToolOutput};
+use crate::newick::{self, Tree};
+use crate::utils;
+
+// Default executable file names
 pub const IQTREE_EXE: &str = "iqtree2";
 pub const ASTRAL_EXE: &str = "astral.sh";
+pub const TRIMAL_EXE: &str = "trimal";
+pub const CLIPKIT_EXE: &str = "clipkit";
 
 // Directories and filenames for species tree estimation
 const SPECIES_TREE_PREFIX: &str = "concat";
@@ -21,88 +27,420 @@ const SPECIES_TREE_OUTPUT_DIR: &str = "iqtree-species-tree";
 
 // Directories and file name for gene tree estimation
 const GENE_TREE_NAME: &str = "genes.treefiles";
+// Gene trees with low-support branches collapsed for ASTRAL
+const COLLAPSED_GENE_TREE_NAME: &str = "genes_collapsed.treefiles";
+// Gene trees without long-branch outlier tips
+const SHRUNK_GENE_TREE_NAME: &str = "genes_shrunk.treefiles";
+const SHRUNK_TAXA_NAME: &str = "shrunk_taxa.tsv";
+const SHRUNK_DIR: &str = "shrunk-alignments";
+// Rooted copies of the final trees
+const ROOTED_DIR: &str = "rooted-trees";
 const GENE_TREE_OUTPUT_DIR: &str = "iqtree-genes";
 const GENE_TREE_DIR: &str = "gene-treefiles";
+// The last section IQ-TREE writes in its .iqtree report
+const IQTREE_REPORT_END: &str = "Total wall-clock time used";
+// Params and alignment hash of a finished locus
+const LOCUS_CHECKPOINT_EXT: &str = "myte";
+const FAILED_LOCI_NAME: &str = "failed_loci.tsv";
+const GENE_TREE_SUMMARY_NAME: &str = "gene_tree_summary.tsv";
+// Number of stderr lines kept for each failed locus
+const FAILED_LOCI_ERR_LINES: usize = 5;
+
+// Alignment validation and the loci left after it
+const VALIDATION_REPORT_NAME: &str = "validation_report.tsv";
+const FILTERED_LOCI_NAME: &str = "filtered_loci.tsv";
+const EXCLUDED_TAXA_NAME: &str = "excluded_taxa.tsv";
+const TAXON_FILTER_DIR: &str = "filtered-alignments";
+
+// Alignment trimming
+const TRIMMED_DIR: &str = "trimmed-alignments";
+const CONVERTED_DIR: &str = "converted-alignments";
+const TRIMMED_EXT: &str = "fas";
+const CONCAT_DIR: &str = "supermatrix";
+const SUMMARY_DIR: &str = "summary";
 
 // Concordance factor estimation
 const CONCORD_FACTOR_OUTPUT_DIR: &str = "iqtree-CF";
 const CONCORD_FACTOR_PREFIX: &str = "concord";
+// Concordance factors per branch parsed from the IQ-TREE outputs
+const CONCORDANCE_TABLE_NAME: &str = "concordance_factors.csv";
+const DISCORDANT_BRANCHES_NAME: &str = "discordant_branches.csv";
 
 // Astral msc constant
 const ASTRAL_TREE_NAME: &str = "msc_astral.tree";
 const ASTRAL_LOG_NAME: &str = "msc_astral.log";
 
-pub fn build_species_tree(path: &str, params: &Option<String>) {
-    let dir_path = Path::new(path);
-    let mut iqtree = SpeciesTree::new(&dir_path, params);
+// Runs IQ-TREE on a supermatrix using its partitions.
+pub fn build_species_tree(
+    matrix: &Path,
+    partitions: &Path,
+    params: &Option<String>,
+    outputs: &OutputDir,
+    executor: &dyn Executor,
+    exes: &Executables,
+) -> Result<()> {
+    let mut iqtree = SpeciesTree::new(matrix, partitions, params, outputs, executor, exes);
     iqtree.print_species_info();
     let msg = format!(
-        "\x1b[0mIQ-TREE is processing species tree for alignments in {}...",
-        path
+        "\x1b[0mIQ-TREE is processing species tree for {}...",
+        matrix.display()
     );
     let spin = iqtree.set_spinner();
     spin.set_message(msg);
-    iqtree.estimate_species_tree();
+    iqtree
+        .estimate_species_tree()
+        .map_err(|err| clear_spinner(&spin, err))?;
     spin.abandon_with_message("Finished estimating species tree!\n");
+    Ok(())
 }
 
-pub fn build_gene_trees(path: &str, params: &Option<String>, input_fmt: &InputFmt) {
-    let mut genes = GeneTrees::new(path, params, input_fmt);
-    let paths = genes.get_alignment_paths();
-    assert!(
-        paths.len() > 1,
-        "Ups... Failed to process file. Less than one alignment found"
-    );
-    genes.create_tree_files_dir();
+// Returns the number of loci that failed.
+// Failed loci are left out of the combined gene trees.
+pub fn build_gene_trees(
+    paths: &[PathBuf],
+    settings: &GeneTreeSettings,
+    outputs: &OutputDir,
+    executor: &dyn Executor,
+    exes: &Executables,
+) -> Result<usize> {
+    let mut genes = GeneTrees::new(settings, outputs, executor, exes);
+    if paths.len() < 2 {
+        return Err(MyteError::Input(format!(
+            "Found {} alignment(s). Gene tree estimation requires at least two",
+            paths.len(),
+        )));
+    }
+    if !executor.is_dry_run() {
+        genes.create_tree_files_dir()?;
+    }
     let num_aln = paths.len();
-    genes.print_genes_info(&path, num_aln);
+    genes.print_genes_info(num_aln);
     let msg = format!(
         "\x1b[0mIQ-TREE is processing gene trees for {} alignments...",
         num_aln
     );
 
+    if executor.is_dry_run() {
+        genes.print_gene_tree_plan(paths)?;
+        return Ok(0);
+    }
+
     let spin = genes.set_spinner();
     spin.set_message(msg);
-    genes.par_process_gene_trees(&paths);
+    // Reports are written even when the run is aborted.
+    let res = genes.par_process_gene_trees(paths);
+    genes
+        .write_summary()
+        .and(genes.write_failure_report())
+        .and(res)
+        .map_err(|err| clear_spinner(&spin, err))?;
 
     let finish_msg = format!(
         "\x1b[0mFinished estimating gene trees for {} alignments!",
         num_aln
     );
     spin.abandon_with_message(finish_msg);
-    genes.combine_gene_trees();
+    genes.print_failure_info();
+    genes.combine_gene_trees(paths)?;
+    Ok(genes.count_failures())
 }
 
-pub fn estimate_concordance_factor(path: &str) {
-    let dir_path = Path::new(path);
-    let mut iqtree = ConcordFactor::new(&dir_path);
+// Returns the trimmed alignments. They are written as FASTA.
+pub fn trim_alignments(
+    paths: &[PathBuf],
+    tool: &TrimTool,
+    params: &Option<String>,
+    outputs: &OutputDir,
+    executor: &dyn Executor,
+    exes: &Executables,
+) -> Result<Vec<PathBuf>> {
+    let mut trimming = Trimming::new(tool, params, outputs, executor, exes);
+    trimming.print_trim_info(paths.len());
+    if !executor.is_dry_run() {
+        trimming.create_trimmed_dir()?;
+    }
+    let msg = format!(
+        "\x1b[0m{} is trimming {} alignments...",
+        tool.name(),
+        paths.len()
+    );
+    let spin = trimming.set_spinner();
+    spin.set_message(msg);
+    let trimmed = trimming
+        .par_trim_alignments(paths)
+        .map_err(|err| clear_spinner(&spin, err))?;
+    spin.abandon_with_message("\x1b[0mFinished trimming alignments!\n");
+    Ok(trimmed)
+}
+
+// Site concordance factors use the partitioned supermatrix,
+// so only the loci left for the analyses are read.
+pub fn estimate_concordance_factor(
+    concat: &ConcatFiles,
+    threads: usize,
+    outputs: &OutputDir,
+    executor: &dyn Executor,
+    exes: &Executables,
+) -> Result<()> {
+    let mut iqtree = ConcordFactor::new(concat, threads, outputs, executor, exes);
     iqtree.print_concord_info();
     let msg = "\x1b[0mIQ-TREE is processing concordance factor...";
     let spin = iqtree.set_spinner();
     spin.set_message(msg);
-    iqtree.estimate_concordance();
+    iqtree
+        .estimate_concordance()
+        .map_err(|err| clear_spinner(&spin, err))?;
     spin.abandon_with_message("\x1b[0mFinished estimating concordance factor!\n");
+    Ok(())
 }
 
-pub fn estimate_msc_tree(path: &str) {
-    let dir = Path::new(path);
-    let mut astral = MSCTree::new(&dir);
+// Gene trees are the combined or the collapsed gene tree file.
+pub fn estimate_msc_tree(
+    gene_trees: &Path,
+    params: &Option<String>,
+    outputs: &OutputDir,
+    executor: &dyn Executor,
+    exes: &Executables,
+) -> Result<()> {
+    let mut astral = MSCTree::new(gene_trees, params, outputs, executor, exes);
     astral.print_msc_info();
     let msg = "\x1b[0mASTRAL is processing MSC tree...";
     let spin = astral.set_spinner();
     spin.set_message(msg);
-    astral.estimate_msc_tree();
+    astral
+        .estimate_msc_tree()
+        .map_err(|err| clear_spinner(&spin, err))?;
     spin.abandon_with_message("\x1b[0mFinished estimating MSC tree!\n");
+    Ok(())
 }
 
-trait Commons {
-    fn get_files(&self, pattern: &str) -> Vec<PathBuf> {
-        glob(pattern)
-            .expect("Failed finding alignment files")
-            .filter_map(|ok| ok.ok())
-            .collect()
+fn clear_spinner(spin: &ProgressBar, err: MyteError) -> MyteError {
+    spin.finish_and_clear();
+    err
+}
+
+// Resolves every output file and directory against the output root.
+#[derive(Debug, Clone)]
+pub struct OutputDir {
+    root: PathBuf,
+}
+
+impl OutputDir {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn species_tree_dir(&self) -> PathBuf {
+        self.root.join(SPECIES_TREE_OUTPUT_DIR)
+    }
+
+    pub fn species_tree_prefix(&self) -> PathBuf {
+        self.species_tree_dir().join(SPECIES_TREE_PREFIX)
+    }
+
+    pub fn species_tree_file(&self) -> PathBuf {
+        self.species_tree_dir()
+            .join(format!("{}.treefile", SPECIES_TREE_PREFIX))
+    }
+
+    // Directories written by the analyses
+    pub fn output_dirs(&self) -> Vec<PathBuf> {
+        vec![
+            self.species_tree_dir(),
+            self.gene_tree_output_dir(),
+            self.gene_tree_dir(),
+            self.concord_dir(),
+            self.concat_dir(),
+            self.summary_dir(),
+            self.taxon_filtered_dir(),
+            self.trimmed_dir(),
+            self.converted_dir(),
+            self.shrunk_dir(),
+            self.rooted_dir(),
+        ]
+    }
+
+    pub fn concat_dir(&self) -> PathBuf {
+        self.root.join(CONCAT_DIR)
+    }
+
+    pub fn trimmed_dir(&self) -> PathBuf {
+        self.root.join(TRIMMED_DIR)
+    }
+
+    pub fn converted_dir(&self) -> PathBuf {
+        self.root.join(CONVERTED_DIR)
+    }
+
+    pub fn summary_dir(&self) -> PathBuf {
+        self.root.join(SUMMARY_DIR)
+    }
+
+    // Alignments left after dropping taxa
+    pub fn taxon_filtered_dir(&self) -> PathBuf {
+        self.root.join(TAXON_FILTER_DIR)
+    }
+
+    pub fn gene_tree_dir(&self) -> PathBuf {
+        self.root.join(GENE_TREE_DIR)
+    }
+
+    pub fn gene_tree_output_dir(&self) -> PathBuf {
+        self.root.join(GENE_TREE_OUTPUT_DIR)
+    }
+
+    pub fn gene_trees_file(&self) -> PathBuf {
+        self.root.join(GENE_TREE_NAME)
+    }
+
+    pub fn collapsed_gene_trees_file(&self) -> PathBuf {
+        self.root.join(COLLAPSED_GENE_TREE_NAME)
+    }
+
+    pub fn shrunk_gene_trees_file(&self) -> PathBuf {
+        self.root.join(SHRUNK_GENE_TREE_NAME)
+    }
+
+    pub fn shrunk_taxa_file(&self) -> PathBuf {
+        self.root.join(SHRUNK_TAXA_NAME)
+    }
+
+    pub fn rooted_dir(&self) -> PathBuf {
+        self.root.join(ROOTED_DIR)
+    }
+
+    // Alignments without the tips removed from their gene trees
+    pub fn shrunk_dir(&self) -> PathBuf {
+        self.root.join(SHRUNK_DIR)
+    }
+
+    pub fn failed_loci_file(&self) -> PathBuf {
+        self.root.join(FAILED_LOCI_NAME)
+    }
+
+    pub fn gene_tree_summary_file(&self) -> PathBuf {
+        self.root.join(GENE_TREE_SUMMARY_NAME)
+    }
+
+    pub fn validation_report_file(&self) -> PathBuf {
+        self.root.join(VALIDATION_REPORT_NAME)
+    }
+
+    pub fn filtered_loci_file(&self) -> PathBuf {
+        self.root.join(FILTERED_LOCI_NAME)
+    }
+
+    pub fn excluded_taxa_file(&self) -> PathBuf {
+        self.root.join(EXCLUDED_TAXA_NAME)
+    }
+
+    pub fn concord_dir(&self) -> PathBuf {
+        self.root.join(CONCORD_FACTOR_OUTPUT_DIR)
+    }
+
+    pub fn concord_prefix(&self) -> PathBuf {
+        self.concord_dir().join(CONCORD_FACTOR_PREFIX)
+    }
+
+    pub fn concord_tree_file(&self) -> PathBuf {
+        self.concord_dir()
+            .join(format!("{}.cf.tree", CONCORD_FACTOR_PREFIX))
+    }
+
+    // Tree labelled with the branch IDs in the statistics
+    pub fn concord_branch_file(&self) -> PathBuf {
+        self.concord_dir()
+            .join(format!("{}.cf.branch", CONCORD_FACTOR_PREFIX))
+    }
+
+    pub fn concord_stat_file(&self) -> PathBuf {
+        self.concord_dir()
+            .join(format!("{}.cf.stat", CONCORD_FACTOR_PREFIX))
+    }
+
+    pub fn concordance_table_file(&self) -> PathBuf {
+        self.root.join(CONCORDANCE_TABLE_NAME)
+    }
+
+    pub fn discordant_branches_file(&self) -> PathBuf {
+        self.root.join(DISCORDANT_BRANCHES_NAME)
     }
 
+    pub fn msc_tree_file(&self) -> PathBuf {
+        self.root.join(ASTRAL_TREE_NAME)
+    }
+
+    pub fn msc_log_file(&self) -> PathBuf {
+        self.root.join(ASTRAL_LOG_NAME)
+    }
+}
+
+// Names or paths of the external tools.
+#[derive(Debug, Clone)]
+pub struct Executables {
+    pub iqtree: String,
+    pub astral: String,
+    pub trimal: String,
+    pub clipkit: String,
+}
+
+impl Default for Executables {
+    fn default() -> Self {
+        Self {
+            iqtree: String::from(IQTREE_EXE),
+            astral: String::from(ASTRAL_EXE),
+            trimal: String::from(TRIMAL_EXE),
+            clipkit: String::from(CLIPKIT_EXE),
+        }
+    }
+}
+
+impl Executables {
+    pub fn iqtree_command(&self) -> ToolCommand {
+        ToolCommand::new(&self.iqtree)
+    }
+
+    // A bare ASTRAL jar runs through java.
+    pub fn astral_command(&self) -> ToolCommand {
+        if self.astral.ends_with(".jar") {
+            let mut cmd = ToolCommand::new("java");
+            cmd.arg("-jar").arg(&self.astral);
+            return cmd;
+        }
+        ToolCommand::new(&self.astral)
+    }
+
+    pub fn trimal_command(&self) -> ToolCommand {
+        ToolCommand::new(&self.trimal)
+    }
+
+    pub fn clipkit_command(&self) -> ToolCommand {
+        ToolCommand::new(&self.clipkit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimTool {
+    Trimal,
+    Clipkit,
+}
+
+impl TrimTool {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrimTool::Trimal => "trimAl",
+            TrimTool::Clipkit => "ClipKIT",
+        }
+    }
+}
+
+trait Commons {
     fn set_spinner(&mut self) -> ProgressBar {
         let spin = ProgressBar::new_spinner();
         spin.enable_steady_tick(150);
@@ -114,199 +452,571 @@ trait Commons {
         spin
     }
 
-    fn check_process_success(&self, out: &Output, path: &Path) {
-        if !out.status.success() {
+    fn check_process_success(&self, out: &ToolOutput, path: &Path) {
+        if !out.success() {
             log::error!(
                 "{}: IQ-TREE failed to process {} (See below).",
                 White.on(Red).paint("ERROR"),
                 path.to_string_lossy()
             );
-            log::error!("{}", std::str::from_utf8(&out.stdout).unwrap());
-            log::error!("{}", std::str::from_utf8(&out.stderr).unwrap());
+            log::error!("{}", out.stdout);
+            log::error!("{}", out.stderr);
         }
     }
 }
 
-pub enum InputFmt {
-    Fasta,
-    Nexus,
-    Phylip,
-}
-
 impl Commons for GeneTrees<'_> {}
 impl Commons for SpeciesTree<'_> {}
 impl Commons for ConcordFactor<'_> {}
 impl Commons for MSCTree<'_> {}
+impl Commons for Trimming<'_> {}
+
+// Handles loci IQ-TREE failed to process.
+// Retries use the fallback params when given,
+// e.g. without bootstrap for alignments that are too small.
+// The run is aborted when more than max_failures loci fail.
+#[derive(Debug, Clone, Default)]
+pub struct FailurePolicy {
+    pub retries: usize,
+    pub fallback: Option<String>,
+    pub max_failures: Option<usize>,
+}
+
+impl FailurePolicy {
+    fn attempts(&self) -> usize {
+        self.retries + 1
+    }
+
+    fn get_params<'p>(&'p self, attempt: usize, params: &'p Option<String>) -> &'p Option<String> {
+        match self.fallback {
+            Some(_) if attempt > 1 => &self.fallback,
+            _ => params,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GeneTreeSettings {
+    pub params: Option<String>,
+    pub policy: FailurePolicy,
+    // Branches with lower support are collapsed
+    // in a separate copy of the combined gene trees.
+    pub collapse_below: Option<f64>,
+    // Skips loci finished with the same params and alignment
+    pub resume: bool,
+}
+
+// The last IQ-TREE attempt for a locus.
+struct LocusRun {
+    locus: String,
+    attempt: usize,
+    params: Option<String>,
+    code: Option<i32>,
+    stderr: String,
+    success: bool,
+}
+
+impl LocusRun {
+    fn new(locus: &str, attempt: usize, params: &Option<String>, out: &ToolOutput) -> Self {
+        Self {
+            locus: String::from(locus),
+            attempt,
+            params: params.clone(),
+            code: out.code,
+            stderr: get_last_lines(out, FAILED_LOCI_ERR_LINES),
+            success: out.success(),
+        }
+    }
+}
 
 struct GeneTrees<'a> {
-    path: &'a str,
-    params: &'a Option<String>,
-    treedir: &'a Path,
-    parent_dir: &'a Path,
-    input_fmt: &'a InputFmt,
+    settings: &'a GeneTreeSettings,
+    treedir: PathBuf,
+    parent_dir: PathBuf,
+    gene_trees: PathBuf,
+    collapsed_trees: PathBuf,
+    executor: &'a dyn Executor,
+    report: PathBuf,
+    summary: PathBuf,
+    exes: &'a Executables,
+    runs: Mutex<Vec<LocusRun>>,
 }
 
 impl<'a> GeneTrees<'a> {
-    fn new(path: &'a str, params: &'a Option<String>, input_fmt: &'a InputFmt) -> Self {
+    fn new(
+        settings: &'a GeneTreeSettings,
+        outputs: &OutputDir,
+        executor: &'a dyn Executor,
+        exes: &'a Executables,
+    ) -> Self {
         Self {
-            path,
-            params,
-            treedir: Path::new(GENE_TREE_DIR),
-            parent_dir: Path::new(GENE_TREE_OUTPUT_DIR),
-            input_fmt,
+            settings,
+            treedir: outputs.gene_tree_dir(),
+            parent_dir: outputs.gene_tree_output_dir(),
+            gene_trees: outputs.gene_trees_file(),
+            collapsed_trees: outputs.collapsed_gene_trees_file(),
+            executor,
+            report: outputs.failed_loci_file(),
+            summary: outputs.gene_tree_summary_file(),
+            exes,
+            runs: Mutex::new(Vec::new()),
         }
     }
 
-    fn get_alignment_paths(&mut self) -> Vec<PathBuf> {
-        let pattern = self.get_pattern();
-        self.get_files(&pattern)
+    fn print_genes_info(&self, aln_size: usize) {
+        log::info!("{:18}: {}", "File counts", aln_size);
+        log::info!("{:18}: IQ-TREE gene tree estimation", "Analyses");
+        log::info!("{:18}: {}\n", "Executable", self.exes.iqtree);
+    }
+
+    fn create_tree_files_dir(&mut self) -> Result<()> {
+        fs::create_dir_all(&self.treedir).map_err(|err| MyteError::io(&self.treedir, err))
+    }
+
+    fn par_process_gene_trees(&self, paths: &[PathBuf]) -> Result<()> {
+        let paths = self.filter_finished_loci(paths);
+        paths.par_iter().try_for_each(|path| {
+            self.check_max_failures()?;
+            if let Some(run) = self.estimate_gene_tree(path)? {
+                self.runs.lock().unwrap().push(run);
+            }
+            self.check_max_failures()
+        })
+    }
+
+    fn count_failures(&self) -> usize {
+        self.runs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|run| !run.success)
+            .count()
     }
 
-    fn get_pattern(&mut self) -> String {
-        match self.input_fmt {
-            InputFmt::Fasta => format!("{}/*.fa*", self.path),
-            InputFmt::Nexus => format!("{}/*.nex*", self.path),
-            InputFmt::Phylip => format!("{}/*.phy*", self.path),
+    // Loci that are already running will still finish
+    // after the limit is reached.
+    fn check_max_failures(&self) -> Result<()> {
+        let failed = self.count_failures();
+        match self.settings.policy.max_failures {
+            Some(max) if failed > max => Err(MyteError::TooManyFailures {
+                failed,
+                max,
+                report: self.report.clone(),
+            }),
+            _ => Ok(()),
         }
     }
 
-    fn print_genes_info<P: AsRef<Path>>(&self, path: &P, aln_size: usize) {
-        log::info!("{:18}: {}", "Alignment path", path.as_ref().display());
-        log::info!("{:18}: {}", "File counts", aln_size);
-        log::info!("{:18}: IQ-TREE gene tree estimation", "Analyses");
-        log::info!("{:18}: {}\n", "Executable", IQTREE_EXE);
+    fn write_failure_report(&self) -> Result<()> {
+        let runs = self.runs.lock().unwrap();
+        let rows: Vec<String> = runs
+            .iter()
+            .filter(|run| !run.success)
+            .map(|failed| {
+                let code = match failed.code {
+                    Some(code) => code.to_string(),
+                    None => String::from("NA"),
+                };
+                format!("{}\t{}\t{}", failed.locus, code, failed.stderr)
+            })
+            .collect();
+        utils::write_report_or_remove(&self.report, &rows, "locus\texit_code\tstderr")
     }
 
-    fn create_tree_files_dir(&mut self) {
-        fs::create_dir_all(&self.treedir).expect("Failed creating a directory for treefiles");
+    // Lists loci estimated in this run and the attempt that finished.
+    // Loci are sorted, so both reports are in the same order.
+    fn write_summary(&self) -> Result<()> {
+        let mut runs = self.runs.lock().unwrap();
+        runs.sort_by(|a, b| a.locus.cmp(&b.locus));
+        self.write_runs(&runs)
+            .map_err(|err| MyteError::io(&self.summary, err))
     }
 
-    fn par_process_gene_trees(&mut self, paths: &[PathBuf]) {
-        paths
-            .par_iter()
-            .for_each(|path| self.estimate_gene_tree(path));
-    }
-
-    fn estimate_gene_tree(&self, path: &Path) {
-        let prefix = path.file_stem().unwrap().to_string_lossy();
-        let iqtree = Process::new(path, self.params);
-        let out = iqtree.run_iqtree(&prefix);
-        self.check_process_success(&out, path);
-        let files = iqtree.get_iqtree_files(&prefix);
-        self.organize_gene_files(&files, &prefix).unwrap();
-    }
-
-    fn organize_gene_files(&self, files: &[PathBuf], prefix: &str) -> Result<()> {
-        let path = self.parent_dir.join(prefix);
-        let dir = Path::new(&path);
-        fs::create_dir_all(dir)?;
-        files.iter().for_each(|file| {
-            let ext = file.extension().unwrap().to_string_lossy();
-            if ext == "treefile" {
-                let outdir = self.treedir.join(file);
-                fs::rename(file, outdir).expect("Failed moving a treefile");
-            } else {
-                let outdir = dir.join(file);
-                fs::rename(file, outdir).expect("Failed moving IQ-TREE files");
+    fn write_runs(&self, runs: &[LocusRun]) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(&self.summary)?);
+        writeln!(writer, "locus\tstatus\tattempt\tparams")?;
+        runs.iter().try_for_each(|run| {
+            let status = if run.success { "success" } else { "failed" };
+            let params = match &run.params {
+                Some(params) => params.as_str(),
+                None => "default",
+            };
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                run.locus, status, run.attempt, params
+            )
+        })?;
+        writer.flush()
+    }
+
+    fn print_failure_info(&self) {
+        let failed = self.count_failures();
+        if failed > 0 {
+            log::warn!(
+                "{:18}: {} loci (See {})\n",
+                "Failed",
+                failed,
+                self.report.display()
+            );
+        }
+    }
+
+    // Loci are listed in order, so the plan is reproducible.
+    fn print_gene_tree_plan(&self, paths: &[PathBuf]) -> Result<()> {
+        self.filter_finished_loci(paths)
+            .iter()
+            .try_for_each(|path| self.estimate_gene_tree(path).map(|_| ()))?;
+        log::info!(
+            "Gene trees will be combined into {}\n",
+            self.gene_trees.display()
+        );
+        Ok(())
+    }
+
+    fn filter_finished_loci<'p>(&self, paths: &'p [PathBuf]) -> Vec<&'p PathBuf> {
+        if !self.settings.resume {
+            return paths.iter().collect();
+        }
+        let remaining: Vec<&PathBuf> = paths
+            .iter()
+            .filter(|path| !self.is_finished_locus(path))
+            .collect();
+        let skipped = paths.len() - remaining.len();
+        if skipped > 0 {
+            log::info!(
+                "{:18}: {} of {} loci have finished gene trees\n",
+                "Skipped",
+                skipped,
+                paths.len()
+            );
+        }
+        remaining
+    }
+
+    // A locus is finished when it has a treefile, IQ-TREE wrote
+    // its report to the end, and it ran with the same params
+    // on the same alignment.
+    fn is_finished_locus(&self, path: &Path) -> bool {
+        let locus = get_locus_name(path);
+        let treefile = self.treedir.join(format!("{}.treefile", locus));
+        let prefix = self.get_locus_prefix(&locus);
+        let report = get_prefixed_path(&prefix, "iqtree");
+        let stamp = fs::read_to_string(get_prefixed_path(&prefix, LOCUS_CHECKPOINT_EXT));
+        checkpoint::is_valid_tree_file(&treefile)
+            && is_complete_iqtree_report(&report)
+            && matches!(stamp, Ok(stamp) if stamp == self.get_locus_stamp(path))
+    }
+
+    fn get_locus_stamp(&self, path: &Path) -> String {
+        let params = |params: &Option<String>| match params {
+            Some(params) => params.clone(),
+            None => String::from("default"),
+        };
+        format!(
+            "params\t{}\nfallback\t{}\nalignment\t{}\n",
+            params(&self.settings.params),
+            params(&self.settings.policy.fallback),
+            Fingerprint::default().add(fs::read(path).unwrap_or_default())
+        )
+    }
+
+    fn write_locus_stamp(&self, path: &Path, prefix: &Path) -> Result<()> {
+        let stamp = get_prefixed_path(prefix, LOCUS_CHECKPOINT_EXT);
+        fs::write(&stamp, self.get_locus_stamp(path)).map_err(|err| MyteError::io(&stamp, err))
+    }
+
+    // IQ-TREE failures for a single locus are returned
+    // instead of stopping the run, so the remaining loci can still finish.
+    // Dry runs only print the first attempt.
+    fn estimate_gene_tree(&self, path: &Path) -> Result<Option<LocusRun>> {
+        let locus = get_locus_name(path);
+        let prefix = self.get_locus_prefix(&locus);
+        if self.executor.is_dry_run() {
+            Process::new(path, &self.settings.params, self.executor, self.exes)
+                .run_iqtree(&prefix)?;
+            return Ok(None);
+        }
+        let dir = self.parent_dir.join(locus.as_ref());
+        fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+        let policy = &self.settings.policy;
+        let attempts = policy.attempts();
+        let mut attempt = 1;
+        loop {
+            let params = policy.get_params(attempt, &self.settings.params);
+            let (run, out) = self.run_attempt(path, &prefix, attempt, params)?;
+            if run.success {
+                self.write_locus_stamp(path, &prefix)?;
+                return Ok(Some(run));
+            }
+            if attempt == attempts {
+                self.check_process_success(&out, path);
+                self.remove_treefile(&locus)?;
+                return Ok(Some(run));
             }
-        });
+            log::warn!(
+                "Attempt {} of {} failed for {}. Retrying...",
+                attempt,
+                attempts,
+                locus
+            );
+            attempt += 1;
+        }
+    }
+
+    // IQ-TREE refuses to overwrite outputs of an earlier run,
+    // so retries and loci with earlier outputs run with -redo.
+    fn run_attempt(
+        &self,
+        path: &Path,
+        prefix: &Path,
+        attempt: usize,
+        params: &Option<String>,
+    ) -> Result<(LocusRun, ToolOutput)> {
+        let locus = get_locus_name(path);
+        let iqtree = Process::new(path, params, self.executor, self.exes);
+        let out = if attempt > 1 || has_iqtree_outputs(prefix) {
+            iqtree.rerun_iqtree(prefix)?
+        } else {
+            iqtree.run_iqtree(prefix)?
+        };
+        let mut run = LocusRun::new(&locus, attempt, params, &out);
+        if run.success && !self.copy_treefile(prefix, &locus)? {
+            run.success = false;
+            run.stderr = String::from("IQ-TREE did not write a treefile");
+        }
+        Ok((run, out))
+    }
+
+    // IQ-TREE appends its own extensions to the prefix,
+    // so locus names with dots are kept as they are.
+    fn get_locus_prefix(&self, locus: &str) -> PathBuf {
+        self.parent_dir.join(locus).join(locus)
+    }
 
+    // Returns false when IQ-TREE did not write the treefile.
+    fn copy_treefile(&self, prefix: &Path, locus: &str) -> Result<bool> {
+        let treefile = PathBuf::from(format!("{}.treefile", prefix.display()));
+        if !treefile.is_file() {
+            return Ok(false);
+        }
+        let target = self.treedir.join(format!("{}.treefile", locus));
+        fs::copy(&treefile, &target).map_err(|err| MyteError::io(&target, err))?;
+        Ok(true)
+    }
+
+    // A treefile left from an earlier run of a failed locus
+    // would otherwise be picked up by the later stages.
+    fn remove_treefile(&self, locus: &str) -> Result<()> {
+        let target = self.treedir.join(format!("{}.treefile", locus));
+        if target.is_file() {
+            fs::remove_file(&target).map_err(|err| MyteError::io(&target, err))?;
+        }
         Ok(())
     }
 
-    fn combine_gene_trees(&mut self) {
-        let pattern = format!("{}/*.treefile", self.treedir.to_string_lossy());
-        let trees = self.get_files(&pattern);
-        let file =
-            File::create(GENE_TREE_NAME).expect("Failed creating a file to store gene trees");
-        let mut treefile = BufWriter::new(file);
-        let num_trees = trees.len();
+    // Trees are parsed first, so a malformed tree file
+    // fails here instead of inside ASTRAL.
+    fn combine_gene_trees(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let tree_files = get_gene_tree_files(paths, &self.treedir);
+        let num_trees = tree_files.len();
         let msg = format!("Combining {} gene trees into a single file...", num_trees);
         let spin = self.set_spinner();
         spin.set_message(msg);
-        trees
-            .iter()
-            .for_each(|tree| self.write_trees(&mut treefile, tree));
+        let mut trees = self
+            .read_trees(&tree_files)
+            .and_then(|trees| newick::write_trees(&self.gene_trees, &trees).map(|_| trees))
+            .map_err(|err| clear_spinner(&spin, err))?;
         let finish_msg = format!("Finished combining {} gene trees!\n", num_trees);
         spin.finish_with_message(finish_msg);
+        if let Some(threshold) = self.settings.collapse_below {
+            let collapsed: usize = trees
+                .iter_mut()
+                .map(|tree| tree.collapse_below(threshold))
+                .sum();
+            newick::write_trees(&self.collapsed_trees, &trees)?;
+            self.print_collapse_info(threshold, collapsed);
+        }
+        Ok(())
+    }
+
+    fn read_trees(&self, tree_files: &[PathBuf]) -> Result<Vec<Tree>> {
+        let mut trees = Vec::with_capacity(tree_files.len());
+        for path in tree_files {
+            trees.extend(Tree::from_file(path)?);
+        }
+        Ok(trees)
+    }
+
+    fn print_collapse_info(&self, threshold: f64, collapsed: usize) {
+        log::info!(
+            "{:18}: {} branches (support < {})",
+            "Collapsed",
+            collapsed,
+            threshold
+        );
+        log::info!(
+            "{:18}: {}\n",
+            "Collapsed trees",
+            self.collapsed_trees.display()
+        );
+    }
+}
+
+struct Trimming<'a> {
+    tool: &'a TrimTool,
+    params: &'a Option<String>,
+    trimmed_dir: PathBuf,
+    executor: &'a dyn Executor,
+    exes: &'a Executables,
+}
+
+impl<'a> Trimming<'a> {
+    fn new(
+        tool: &'a TrimTool,
+        params: &'a Option<String>,
+        outputs: &OutputDir,
+        executor: &'a dyn Executor,
+        exes: &'a Executables,
+    ) -> Self {
+        Self {
+            tool,
+            params,
+            trimmed_dir: outputs.trimmed_dir(),
+            executor,
+            exes,
+        }
     }
 
-    fn write_trees<W: Write>(&self, treefile: &mut W, tree_path: &Path) {
-        let mut content = String::new();
-        let mut tree = File::open(tree_path).expect("Failed accessing a treefile");
-        tree.read_to_string(&mut content)
-            .expect("Failed reading treefiles");
-        writeln!(treefile, "{}", content.trim()).unwrap();
+    fn print_trim_info(&self, aln_size: usize) {
+        log::info!("{:18}: {}", "File counts", aln_size);
+        log::info!("{:18}: {} alignment trimming", "Analyses", self.tool.name());
+        log::info!("{:18}: {}\n", "Executable", self.get_exe());
+    }
+
+    fn create_trimmed_dir(&self) -> Result<()> {
+        // Loci trimmed in an earlier run may have been filtered out since.
+        if self.trimmed_dir.is_dir() {
+            fs::remove_dir_all(&self.trimmed_dir)
+                .map_err(|err| MyteError::io(&self.trimmed_dir, err))?;
+        }
+        fs::create_dir_all(&self.trimmed_dir).map_err(|err| MyteError::io(&self.trimmed_dir, err))
+    }
+
+    // The trimmed alignments keep the order of the input.
+    // Dry runs trim in order, so the plan is reproducible.
+    fn par_trim_alignments(&self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        if self.executor.is_dry_run() {
+            return paths.iter().map(|path| self.trim_alignment(path)).collect();
+        }
+        paths
+            .par_iter()
+            .map(|path| self.trim_alignment(path))
+            .collect()
+    }
+
+    fn trim_alignment(&self, path: &Path) -> Result<PathBuf> {
+        let output = self
+            .trimmed_dir
+            .join(format!("{}.{}", get_locus_name(path), TRIMMED_EXT));
+        let trimmer = Process::new(path, self.params, self.executor, self.exes);
+        let out = trimmer.run_trimmer(self.tool, &output)?;
+        check_tool_output(self.get_exe(), &out, path)?;
+        Ok(output)
+    }
+
+    fn get_exe(&self) -> &str {
+        match self.tool {
+            TrimTool::Trimal => &self.exes.trimal,
+            TrimTool::Clipkit => &self.exes.clipkit,
+        }
     }
 }
 
 struct SpeciesTree<'a> {
     path: &'a Path,
-    prefix: &'a str,
+    partitions: &'a Path,
     params: &'a Option<String>,
-    outdir: &'a Path,
+    outputs: &'a OutputDir,
+    executor: &'a dyn Executor,
+    exes: &'a Executables,
 }
 
 impl<'a> SpeciesTree<'a> {
-    fn new(path: &'a Path, params: &'a Option<String>) -> Self {
+    fn new(
+        path: &'a Path,
+        partitions: &'a Path,
+        params: &'a Option<String>,
+        outputs: &'a OutputDir,
+        executor: &'a dyn Executor,
+        exes: &'a Executables,
+    ) -> Self {
         Self {
             path,
-            prefix: SPECIES_TREE_PREFIX,
-            outdir: Path::new(SPECIES_TREE_OUTPUT_DIR),
+            partitions,
             params,
+            outputs,
+            executor,
+            exes,
         }
     }
 
-    fn estimate_species_tree(&mut self) {
-        let iqtree = Process::new(self.path, self.params);
-        let out = iqtree.run_iqtree(&self.prefix);
-        self.check_process_success(&out, self.path);
-        let files = iqtree.get_iqtree_files(&self.prefix);
-        self.organize_species_files(&files)
-            .expect("Failed moving species tree files");
+    fn estimate_species_tree(&mut self) -> Result<()> {
+        if !self.executor.is_dry_run() {
+            let dir = self.outputs.species_tree_dir();
+            fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+        }
+        let iqtree = Process::new(self.path, self.params, self.executor, self.exes);
+        let out =
+            iqtree.run_iqtree_partitioned(&self.outputs.species_tree_prefix(), self.partitions)?;
+        check_tool_output(&self.exes.iqtree, &out, self.path)
     }
 
     fn print_species_info(&self) {
         log::info!("{:18}: IQ-TREE species tree estimation", "Analyses");
-        log::info!("{:18}: {}\n", "Executable", IQTREE_EXE);
-    }
-
-    fn organize_species_files(&self, files: &[PathBuf]) -> Result<()> {
-        fs::create_dir_all(&self.outdir)?;
-        files.iter().for_each(|file| {
-            let outdir = self.outdir.join(file);
-            let ext = file.extension().unwrap().to_string_lossy();
-            if ext != "treefile" {
-                fs::rename(file, outdir).expect("Failed moving IQ-TREE files");
-            }
-        });
-
-        Ok(())
+        log::info!("{:18}: {}\n", "Executable", self.exes.iqtree);
     }
 }
 
 struct ConcordFactor<'a> {
-    path: &'a Path,
-    outdir: &'a Path,
-    prefix: &'a str,
+    concat: &'a ConcatFiles,
+    threads: usize,
+    outputs: &'a OutputDir,
+    executor: &'a dyn Executor,
+    exes: &'a Executables,
 }
 
 impl<'a> ConcordFactor<'a> {
-    fn new(path: &'a Path) -> Self {
+    fn new(
+        concat: &'a ConcatFiles,
+        threads: usize,
+        outputs: &'a OutputDir,
+        executor: &'a dyn Executor,
+        exes: &'a Executables,
+    ) -> Self {
         Self {
-            path,
-            outdir: Path::new(CONCORD_FACTOR_OUTPUT_DIR),
-            prefix: CONCORD_FACTOR_PREFIX,
+            concat,
+            threads,
+            outputs,
+            executor,
+            exes,
         }
     }
 
-    fn estimate_concordance(&mut self) {
-        let iqtree = Process::new(self.path, &None);
-        let out = iqtree.run_iqtree_concord(&self.prefix);
-        self.check_process_success(&out, self.path);
-        let files = iqtree.get_iqtree_files(&self.prefix);
-        self.organize_cf_files(&files)
-            .expect("Failed moving concordance factor files");
+    fn estimate_concordance(&mut self) -> Result<()> {
+        if !self.executor.is_dry_run() {
+            let dir = self.outputs.concord_dir();
+            fs::create_dir_all(&dir).map_err(|err| MyteError::io(&dir, err))?;
+        }
+        let iqtree = Process::new(&self.concat.matrix, &None, self.executor, self.exes);
+        let out = iqtree.run_iqtree_concord(
+            &self.outputs.concord_prefix(),
+            &self.outputs.species_tree_file(),
+            &self.outputs.gene_trees_file(),
+            &self.concat.partitions,
+            self.threads,
+        )?;
+        check_tool_output(&self.exes.iqtree, &out, &self.concat.matrix)
     }
 
     fn print_concord_info(&self) {
@@ -314,127 +1024,260 @@ impl<'a> ConcordFactor<'a> {
             "{:18}: IQ-TREE gene and site concordance factors",
             "Analyses"
         );
-        log::info!("{:18}: {}\n", "Executable", IQTREE_EXE);
-    }
-
-    fn organize_cf_files(&self, files: &[PathBuf]) -> Result<()> {
-        fs::create_dir_all(&self.outdir)?;
-        files.iter().for_each(|file| {
-            let outdir = self.outdir.join(file);
-            let ext = file.extension().unwrap().to_string_lossy();
-            if ext != "tre" {
-                fs::rename(file, outdir).expect("Failed moving IQ-TREE files");
-            }
-        });
-
-        Ok(())
+        log::info!("{:18}: {}\n", "Executable", self.exes.iqtree);
     }
 }
 
 struct MSCTree<'a> {
-    path: &'a Path,
-    astral_out: &'a str,
+    gene_trees: &'a Path,
+    params: &'a Option<String>,
+    outputs: &'a OutputDir,
+    executor: &'a dyn Executor,
+    exes: &'a Executables,
 }
 
 impl<'a> MSCTree<'a> {
-    fn new(path: &'a Path) -> Self {
+    fn new(
+        gene_trees: &'a Path,
+        params: &'a Option<String>,
+        outputs: &'a OutputDir,
+        executor: &'a dyn Executor,
+        exes: &'a Executables,
+    ) -> Self {
         Self {
-            path,
-            astral_out: ASTRAL_LOG_NAME,
+            gene_trees,
+            params,
+            outputs,
+            executor,
+            exes,
         }
     }
 
-    fn estimate_msc_tree(&self) {
-        let astral = Process::new(self.path, &None);
-        let out = astral.run_astral();
-        self.check_process_success(&out, self.path);
-        if out.status.success() {
-            self.write_astral_output(&out);
+    fn estimate_msc_tree(&self) -> Result<()> {
+        let astral = Process::new(self.gene_trees, self.params, self.executor, self.exes);
+        let out = astral.run_astral(self.gene_trees, &self.outputs.msc_tree_file())?;
+        check_tool_output(&self.exes.astral, &out, self.gene_trees)?;
+        if !self.executor.is_dry_run() {
+            self.write_astral_output(&out)?;
         }
+        Ok(())
     }
 
     fn print_msc_info(&self) {
         log::info!("{:18}: Astral MSC", "Analyses");
-        log::info!("{:18}: {}\n", "Executable", ASTRAL_EXE);
+        log::info!("{:18}: {}", "Gene trees", self.gene_trees.display());
+        log::info!("{:18}: {}\n", "Executable", self.exes.astral);
     }
 
-    fn write_astral_output(&self, out: &Output) {
-        let mut asral_log = File::create(&self.astral_out).expect("Failed writing Astral log");
-        write!(asral_log, "{}", str::from_utf8(&out.stderr).unwrap()).unwrap();
+    fn write_astral_output(&self, out: &ToolOutput) -> Result<()> {
+        let log_path = self.outputs.msc_log_file();
+        fs::write(&log_path, &out.stderr).map_err(|err| MyteError::io(&log_path, err))
     }
 }
 
-impl Commons for Process<'_> {}
+pub fn get_locus_name(path: &Path) -> std::borrow::Cow<'_, str> {
+    match path.file_stem() {
+        Some(stem) => stem.to_string_lossy(),
+        None => path.to_string_lossy(),
+    }
+}
+
+// Returns the treefiles of the alignments, so trees left from loci
+// filtered out since an earlier run are not included.
+pub fn get_gene_tree_files(paths: &[PathBuf], treedir: &Path) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .map(|path| treedir.join(format!("{}.treefile", get_locus_name(path))))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+fn check_tool_output(exe: &str, out: &ToolOutput, input: &Path) -> Result<()> {
+    if out.success() {
+        return Ok(());
+    }
+    Err(MyteError::ToolFailed {
+        exe: String::from(exe),
+        input: input.to_path_buf(),
+        code: out.code,
+        stderr: out.stderr.clone(),
+    })
+}
+
+// IQ-TREE prints most errors to stdout,
+// which is used when stderr is empty.
+fn get_last_lines(out: &ToolOutput, n: usize) -> String {
+    let text = if out.stderr.trim().is_empty() {
+        &out.stdout
+    } else {
+        &out.stderr
+    };
+    let lines: Vec<&str> = text
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let start = lines.len().saturating_sub(n);
+    lines[start..].join(" | ").replace('\t', " ")
+}
+
+// IQ-TREE appends its own extensions to the prefix.
+fn get_prefixed_path(prefix: &Path, ext: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", prefix.display(), ext))
+}
+
+fn has_iqtree_outputs(prefix: &Path) -> bool {
+    ["ckp.gz", "iqtree"]
+        .iter()
+        .any(|ext| get_prefixed_path(prefix, ext).is_file())
+}
+
+fn is_complete_iqtree_report(path: &Path) -> bool {
+    match fs::read_to_string(path) {
+        Ok(content) => content.contains(IQTREE_REPORT_END),
+        Err(_) => false,
+    }
+}
+
+fn add_params(cmd: &mut ToolCommand, params: &str) {
+    params.split_whitespace().for_each(|param| {
+        cmd.arg(param);
+    });
+}
 
 struct Process<'a> {
     path: &'a Path,
     params: &'a Option<String>,
+    executor: &'a dyn Executor,
+    exes: &'a Executables,
 }
 
 impl<'a> Process<'a> {
-    fn new(path: &'a Path, params: &'a Option<String>) -> Self {
-        Self { path, params }
+    fn new(
+        path: &'a Path,
+        params: &'a Option<String>,
+        executor: &'a dyn Executor,
+        exes: &'a Executables,
+    ) -> Self {
+        Self {
+            path,
+            params,
+            executor,
+            exes,
+        }
     }
 
-    fn run_iqtree(&self, prefix: &str) -> Output {
-        let mut out = Command::new(IQTREE_EXE);
-        out.arg("-s").arg(self.path).arg("--prefix").arg(prefix);
-        self.get_thread_num(&mut out);
-        self.get_iqtree_params(&mut out);
-        out.output().expect("Failed to run IQ-TREE")
+    fn run_iqtree(&self, prefix: &Path) -> Result<ToolOutput> {
+        let cmd = self.get_iqtree_cmd(prefix);
+        self.execute(&self.exes.iqtree, &cmd)
     }
 
-    fn run_iqtree_concord(&self, prefix: &str) -> Output {
-        let cores = num_cpus::get_physical();
-        let mut out = Command::new(IQTREE_EXE);
-        out.arg("-t")
-            .arg("concat.treefile")
+    fn run_iqtree_partitioned(&self, prefix: &Path, partitions: &Path) -> Result<ToolOutput> {
+        let mut cmd = self.get_iqtree_cmd(prefix);
+        cmd.arg("-p").arg(partitions);
+        self.execute(&self.exes.iqtree, &cmd)
+    }
+
+    fn rerun_iqtree(&self, prefix: &Path) -> Result<ToolOutput> {
+        let mut cmd = self.get_iqtree_cmd(prefix);
+        if !cmd.args().iter().any(|arg| arg == "-redo") {
+            cmd.arg("-redo");
+        }
+        self.execute(&self.exes.iqtree, &cmd)
+    }
+
+    fn get_iqtree_cmd(&self, prefix: &Path) -> ToolCommand {
+        let mut cmd = self.exes.iqtree_command();
+        cmd.arg("-s").arg(self.path).arg("--prefix").arg(prefix);
+        self.get_thread_num(&mut cmd);
+        self.get_iqtree_params(&mut cmd);
+        cmd
+    }
+
+    fn run_iqtree_concord(
+        &self,
+        prefix: &Path,
+        species_tree: &Path,
+        gene_trees: &Path,
+        partitions: &Path,
+        threads: usize,
+    ) -> Result<ToolOutput> {
+        let mut cmd = self.exes.iqtree_command();
+        cmd.arg("-t")
+            .arg(species_tree)
             .arg("--gcf")
-            .arg(GENE_TREE_NAME)
+            .arg(gene_trees)
+            .arg("-s")
+            .arg(self.path)
             .arg("-p")
-            .arg(&self.path)
+            .arg(partitions)
             .arg("--scf")
             .arg("100")
             .arg("-T")
-            .arg(cores.to_string())
+            .arg(threads.to_string())
             .arg("--prefix")
-            .arg(prefix)
-            .output()
-            .expect("Failed to run IQ-TREE concordance factors")
+            .arg(prefix);
+        self.execute(&self.exes.iqtree, &cmd)
     }
 
-    fn run_astral(&self) -> Output {
-        let mut out = Command::new(ASTRAL_EXE);
-        out.arg("-i")
-            .arg(GENE_TREE_NAME)
-            .arg("-o")
-            .arg(ASTRAL_TREE_NAME)
-            .output()
-            .expect("Failed to run Astral")
+    fn run_astral(&self, gene_trees: &Path, output: &Path) -> Result<ToolOutput> {
+        let mut cmd = self.exes.astral_command();
+        cmd.arg("-i").arg(gene_trees).arg("-o").arg(output);
+        if let Some(params) = self.params {
+            add_params(&mut cmd, params);
+        }
+        self.execute(&self.exes.astral, &cmd)
     }
 
-    fn get_iqtree_params(&self, out: &mut Command) {
-        match self.params {
-            Some(param) => {
-                let params: Vec<&str> = param.split_whitespace().collect();
-                params.iter().for_each(|param| {
-                    out.arg(param);
-                });
+    // Both tools write FASTA, so the trimmed alignments
+    // are parsed the same way whatever the input format.
+    fn run_trimmer(&self, tool: &TrimTool, output: &Path) -> Result<ToolOutput> {
+        let (exe, mut cmd, default_params) = match tool {
+            TrimTool::Trimal => {
+                let mut cmd = self.exes.trimal_command();
+                cmd.arg("-in")
+                    .arg(self.path)
+                    .arg("-out")
+                    .arg(output)
+                    .arg("-fasta");
+                (&self.exes.trimal, cmd, Some("-automated1"))
             }
-            None => {
-                out.arg("-B").arg("1000");
+            TrimTool::Clipkit => {
+                let mut cmd = self.exes.clipkit_command();
+                cmd.arg(self.path)
+                    .arg("-o")
+                    .arg(output)
+                    .arg("-of")
+                    .arg("fasta");
+                (&self.exes.clipkit, cmd, None)
             }
+        };
+        match (self.params, default_params) {
+            (Some(params), _) => add_params(&mut cmd, params),
+            (None, Some(params)) => add_params(&mut cmd, params),
+            (None, None) => (),
         }
+        self.execute(exe, &cmd)
     }
 
-    fn get_iqtree_files(&self, prefix: &str) -> Vec<PathBuf> {
-        let pattern = format!("{}.*", prefix);
-        self.get_files(&pattern)
+    fn execute(&self, exe: &str, cmd: &ToolCommand) -> Result<ToolOutput> {
+        self.executor
+            .execute(cmd)
+            .map_err(|err| MyteError::spawn(exe, err))
+    }
+
+    fn get_iqtree_params(&self, cmd: &mut ToolCommand) {
+        match self.params {
+            Some(params) => add_params(cmd, params),
+            None => {
+                cmd.arg("-B").arg("1000");
+            }
+        }
     }
 
-    fn get_thread_num(&self, out: &mut Command) {
+    fn get_thread_num(&self, cmd: &mut ToolCommand) {
         if self.params.is_none() {
-            out.arg("-T").arg("1");
+            cmd.arg("-T").arg("1");
         }
     }
 }
@@ -442,23 +1285,376 @@ impl<'a> Process<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::concat::{write_supermatrix, PartitionFmt};
+    use crate::executor::mock::MockExecutor;
+    use crate::executor::{DryRunExecutor, SystemExecutor};
+    use crate::input::{find_alignments, InputFmt, InputOptions};
+    use crate::writer::OutputFmt;
 
-    const INPUT_FMT: InputFmt = InputFmt::Nexus;
+    fn test_alignments() -> Vec<PathBuf> {
+        find_alignments(
+            &[String::from("test_files")],
+            &InputFmt::Nexus,
+            &InputOptions::default(),
+        )
+        .unwrap()
+        .alignments
+    }
 
     #[test]
-    fn get_gene_paths_test() {
-        let path = "test_files";
-        let mut genes = GeneTrees::new(path, &None, &INPUT_FMT);
-        let gene_paths = genes.get_alignment_paths();
+    fn gene_tree_error_test() {
+        let res = build_gene_trees(
+            &[],
+            &GeneTreeSettings::default(),
+            &OutputDir::new("."),
+            &SystemExecutor,
+            &Executables::default(),
+        );
+        assert!(matches!(res, Err(MyteError::Input(_))));
+    }
 
-        assert_eq!(2, gene_paths.len());
+    #[test]
+    fn unfinished_locus_test() {
+        let outputs = OutputDir::new(".");
+        let exes = Executables::default();
+        let settings = GeneTreeSettings {
+            resume: true,
+            ..GeneTreeSettings::default()
+        };
+        let genes = GeneTrees::new(&settings, &outputs, &SystemExecutor, &exes);
+        let path = Path::new("test_files/gene_1.nexus");
+        assert!(!genes.is_finished_locus(path));
     }
 
     #[test]
-    #[should_panic]
-    fn gene_tree_panic_test() {
-        let path = ".";
-        build_gene_trees(path, &None, &INPUT_FMT);
+    fn output_dir_test() {
+        let outputs = OutputDir::new("results");
+        let exes = Executables::default();
+        let settings = GeneTreeSettings::default();
+        assert_eq!(
+            Path::new("results/iqtree-species-tree/concat.treefile"),
+            outputs.species_tree_file()
+        );
+        assert_eq!(
+            Path::new("results/iqtree-genes/gene_1/gene_1"),
+            GeneTrees::new(&settings, &outputs, &SystemExecutor, &exes).get_locus_prefix("gene_1")
+        );
+    }
+
+    #[test]
+    fn auto_flow_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let executor = MockExecutor::new();
+        let exes = Executables::default();
+        let concat = write_supermatrix(
+            &test_alignments(),
+            &InputFmt::Nexus,
+            &outputs,
+            &OutputFmt::Nexus,
+            &PartitionFmt::Nexus,
+            executor.is_dry_run(),
+        )
+        .unwrap();
+        build_species_tree(
+            &concat.matrix,
+            &concat.partitions,
+            &None,
+            &outputs,
+            &executor,
+            &exes,
+        )
+        .unwrap();
+        build_gene_trees(
+            &test_alignments(),
+            &GeneTreeSettings {
+                collapse_below: Some(50.0),
+                ..GeneTreeSettings::default()
+            },
+            &outputs,
+            &executor,
+            &Executables::default(),
+        )
+        .unwrap();
+        estimate_concordance_factor(&concat, 4, &outputs, &executor, &exes).unwrap();
+        let gene_trees = outputs.collapsed_gene_trees_file();
+        estimate_msc_tree(&gene_trees, &None, &outputs, &executor, &exes).unwrap();
+
+        let commands = executor.commands();
+        assert_eq!(5, commands.len());
+        assert_eq!(IQTREE_EXE, commands[0].program());
+        assert_eq!(ASTRAL_EXE, commands[4].program());
+        assert!(commands[0].has_flag("-p"));
+        assert!(commands[3].has_flag("--gcf"));
+        assert_eq!(
+            Some(concat.partitions.to_string_lossy().as_ref()),
+            commands[3].value_of("-p")
+        );
+        assert_eq!(
+            Some(concat.matrix.to_string_lossy().as_ref()),
+            commands[3].value_of("-s")
+        );
+        assert!(checkpoint::is_valid_tree_file(&outputs.species_tree_file()));
+        assert!(checkpoint::is_valid_tree_file(&outputs.concord_tree_file()));
+        assert!(checkpoint::is_valid_tree_file(&outputs.msc_tree_file()));
+        let gene_trees = fs::read_to_string(outputs.gene_trees_file()).unwrap();
+        assert_eq!(2, gene_trees.lines().count());
+        let collapsed = fs::read_to_string(outputs.collapsed_gene_trees_file()).unwrap();
+        assert_eq!(gene_trees, collapsed);
+        let branches =
+            crate::concordance::summarize_concordance(&outputs, &Default::default(), false)
+                .unwrap();
+        assert_eq!(vec!["C", "D"], branches[0].clade);
+        let collapsed_file = outputs.collapsed_gene_trees_file();
+        assert_eq!(
+            Some(collapsed_file.to_string_lossy().as_ref()),
+            commands[4].value_of("-i")
+        );
+    }
+
+    #[test]
+    fn trim_alignments_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let executor = MockExecutor::new();
+        let exes = Executables::default();
+        let paths = test_alignments();
+        let trimmed =
+            trim_alignments(&paths, &TrimTool::Trimal, &None, &outputs, &executor, &exes).unwrap();
+        assert_eq!(outputs.trimmed_dir().join("gene_1.fas"), trimmed[0]);
+        assert!(trimmed.iter().all(|path| path.is_file()));
+        let params = Some(String::from("-m kpic-gappy"));
+        trim_alignments(
+            &paths,
+            &TrimTool::Clipkit,
+            &params,
+            &outputs,
+            &executor,
+            &exes,
+        )
+        .unwrap();
+
+        let commands = executor.commands();
+        let trimal = commands
+            .iter()
+            .find(|cmd| cmd.program() == TRIMAL_EXE)
+            .unwrap();
+        assert!(trimal.has_flag("-automated1"));
+        let clipkit = commands
+            .iter()
+            .find(|cmd| cmd.program() == CLIPKIT_EXE)
+            .unwrap();
+        assert_eq!(Some("kpic-gappy"), clipkit.value_of("-m"));
+        assert_eq!(Some("fasta"), clipkit.value_of("-of"));
+    }
+
+    #[test]
+    fn skip_finished_loci_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let executor = MockExecutor::new();
+        let run = |settings: &GeneTreeSettings| {
+            build_gene_trees(
+                &test_alignments(),
+                settings,
+                &outputs,
+                &executor,
+                &Executables::default(),
+            )
+            .unwrap();
+            executor.commands().len()
+        };
+        let mut settings = GeneTreeSettings::default();
+        assert_eq!(2, run(&settings));
+        settings.resume = true;
+        assert_eq!(2, run(&settings));
+        // Loci finished with other params are estimated again
+        settings.params = Some(String::from("-B 1000"));
+        assert_eq!(4, run(&settings));
+        assert!(executor.commands()[3].has_flag("-redo"));
+        assert_eq!(4, run(&settings));
+        settings.resume = false;
+        assert_eq!(6, run(&settings));
+    }
+
+    #[test]
+    fn failed_loci_report_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let executor = MockExecutor::failing(&["gene_1"]);
+        let failed = build_gene_trees(
+            &test_alignments(),
+            &GeneTreeSettings::default(),
+            &outputs,
+            &executor,
+            &Executables::default(),
+        )
+        .unwrap();
+        assert_eq!(1, failed);
+        let report = fs::read_to_string(outputs.failed_loci_file()).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(
+            "gene_1\t2\tERROR: Mock alignment error | ERROR: Mock exit",
+            lines[1]
+        );
+        let gene_trees = fs::read_to_string(outputs.gene_trees_file()).unwrap();
+        assert_eq!(1, gene_trees.lines().count());
+    }
+
+    #[test]
+    fn combine_current_loci_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let treedir = outputs.gene_tree_dir();
+        fs::create_dir_all(&treedir).unwrap();
+        // Left from loci filtered out or failed since an earlier run
+        fs::write(treedir.join("gene_0.treefile"), "(A,B,C);\n").unwrap();
+        fs::write(treedir.join("gene_1.treefile"), "(A,B,C);\n").unwrap();
+        let executor = MockExecutor::failing(&["gene_1"]);
+        build_gene_trees(
+            &test_alignments(),
+            &GeneTreeSettings::default(),
+            &outputs,
+            &executor,
+            &Executables::default(),
+        )
+        .unwrap();
+        let gene_trees = fs::read_to_string(outputs.gene_trees_file()).unwrap();
+        assert_eq!(1, gene_trees.lines().count());
+    }
+
+    #[test]
+    fn max_failures_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let executor = MockExecutor::failing(&["gene_1"]);
+        let res = build_gene_trees(
+            &test_alignments(),
+            &GeneTreeSettings {
+                policy: FailurePolicy {
+                    max_failures: Some(0),
+                    ..FailurePolicy::default()
+                },
+                ..GeneTreeSettings::default()
+            },
+            &outputs,
+            &executor,
+            &Executables::default(),
+        );
+        assert!(matches!(res, Err(MyteError::TooManyFailures { .. })));
+        assert!(outputs.failed_loci_file().is_file());
+    }
+
+    #[test]
+    fn retry_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let executor = MockExecutor::failing(&["gene_1"]);
+        let policy = FailurePolicy {
+            retries: 2,
+            ..FailurePolicy::default()
+        };
+        let failed = build_gene_trees(
+            &test_alignments(),
+            &GeneTreeSettings {
+                policy,
+                ..GeneTreeSettings::default()
+            },
+            &outputs,
+            &executor,
+            &Executables::default(),
+        )
+        .unwrap();
+        assert_eq!(1, failed);
+        let commands = executor.commands();
+        assert_eq!(4, commands.len());
+        assert_eq!(
+            2,
+            commands.iter().filter(|cmd| cmd.has_flag("-redo")).count()
+        );
+    }
+
+    #[test]
+    fn retry_fallback_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let executor = MockExecutor::failing(&["-B"]);
+        let params = Some(String::from("-B 1000"));
+        let policy = FailurePolicy {
+            retries: 1,
+            fallback: Some(String::from("-m MFP")),
+            max_failures: None,
+        };
+        let failed = build_gene_trees(
+            &test_alignments(),
+            &GeneTreeSettings {
+                params,
+                policy,
+                ..GeneTreeSettings::default()
+            },
+            &outputs,
+            &executor,
+            &Executables::default(),
+        )
+        .unwrap();
+        assert_eq!(0, failed);
+        let summary = fs::read_to_string(outputs.gene_tree_summary_file()).unwrap();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!("gene_1\tsuccess\t2\t-m MFP", lines[1]);
+        assert!(!outputs.failed_loci_file().exists());
+    }
+
+    #[test]
+    fn dry_run_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path().join("results"));
+        let executor = DryRunExecutor::new();
+        let exes = Executables::default();
+        let concat = write_supermatrix(
+            &test_alignments(),
+            &InputFmt::Nexus,
+            &outputs,
+            &OutputFmt::Nexus,
+            &PartitionFmt::Nexus,
+            executor.is_dry_run(),
+        )
+        .unwrap();
+        build_species_tree(
+            &concat.matrix,
+            &concat.partitions,
+            &None,
+            &outputs,
+            &executor,
+            &exes,
+        )
+        .unwrap();
+        build_gene_trees(
+            &test_alignments(),
+            &GeneTreeSettings::default(),
+            &outputs,
+            &executor,
+            &Executables::default(),
+        )
+        .unwrap();
+        estimate_concordance_factor(&concat, 4, &outputs, &executor, &exes).unwrap();
+        let gene_trees = outputs.gene_trees_file();
+        estimate_msc_tree(&gene_trees, &None, &outputs, &executor, &exes).unwrap();
+        assert!(!outputs.root().exists());
+    }
+
+    #[test]
+    fn astral_jar_test() {
+        let exes = Executables {
+            iqtree: String::from("iqtree3"),
+            astral: String::from("astral.5.7.8.jar"),
+            ..Executables::default()
+        };
+        assert_eq!(
+            "java -jar astral.5.7.8.jar",
+            exes.astral_command().to_string()
+        );
+        assert_eq!("iqtree3", exes.iqtree_command().to_string());
     }
 
     #[test]
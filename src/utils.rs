@@ -1,16 +1,20 @@
+use std::fs::{self, File};
 use std::io::{self, Result, Write};
 use std::iter;
+use std::path::Path;
 
 use ansi_term::Colour::Yellow;
-use chrono::{Local, NaiveTime};
+use chrono::Local;
 use sysinfo::{System, SystemExt};
 
+use crate::error::MyteError;
+
+// Hours are not wrapped, so runs longer than a day still print.
 fn parse_duration(duration: u64) -> String {
-    let sec = (duration % 60) as u32;
-    let min = ((duration / 60) % 60) as u32;
-    let hours = ((duration / 60) / 60) as u32;
-    let time = NaiveTime::from_hms_opt(hours, min, sec).expect("Invalid time");
-    time.format("%H:%M:%S").to_string()
+    let sec = duration % 60;
+    let min = (duration / 60) % 60;
+    let hours = (duration / 60) / 60;
+    format!("{:02}:{:02}:{:02}", hours, min, sec)
 }
 
 pub fn print_formatted_duration(duration: u64) {
@@ -21,7 +25,33 @@ pub fn print_formatted_duration(duration: u64) {
 pub fn print_divider(text: &str, len: usize) {
     let sym = '=';
     let mut header = PrettyDivider::new(text, sym, len);
-    header.print_header().unwrap();
+    // A closed stdout should not stop the analyses.
+    header.print_header().ok();
+}
+
+// Writes a tab or comma separated report with one row per line.
+// A stale report from an earlier run is removed when there are no rows,
+// so the report always matches the current run.
+pub fn write_report_or_remove(
+    path: &Path,
+    rows: &[String],
+    header: &str,
+) -> crate::error::Result<()> {
+    if rows.is_empty() {
+        if path.is_file() {
+            fs::remove_file(path).map_err(|err| MyteError::io(path, err))?;
+        }
+        return Ok(());
+    }
+    write_rows(path, rows, header).map_err(|err| MyteError::io(path, err))
+}
+
+fn write_rows(path: &Path, rows: &[String], header: &str) -> Result<()> {
+    let mut writer = io::BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", header)?;
+    rows.iter()
+        .try_for_each(|row| writeln!(writer, "{}", row))?;
+    writer.flush()
 }
 
 pub fn get_system_info() {
@@ -57,10 +87,7 @@ fn get_os_name(sysinfo: &System) -> String {
 }
 
 fn get_os_version(sysinfo: &System) -> String {
-    match sysinfo.os_version() {
-        Some(i) => i,
-        None => String::from(""),
-    }
+    sysinfo.os_version().unwrap_or_default()
 }
 
 fn get_kernel_version(sysinfo: &System) -> String {
@@ -106,9 +133,9 @@ impl PrettyDivider {
     }
 
     fn print_with_symbol<W: Write>(&mut self, handle: &mut W) -> Result<()> {
-        self.print_symbols(handle);
+        self.print_symbols(handle)?;
         write!(handle, " {} ", self.text)?;
-        self.print_symbols(handle);
+        self.print_symbols(handle)?;
 
         if self.text_len % 2 != 0 {
             write!(handle, "{}", self.sym)?;
@@ -128,9 +155,9 @@ impl PrettyDivider {
         }
     }
 
-    fn print_symbols<W: Write>(&self, io: &mut W) {
+    fn print_symbols<W: Write>(&self, io: &mut W) -> Result<()> {
         let sym: String = iter::repeat(self.sym).take(self.sym_len).collect();
-        write!(io, "{}", sym).unwrap();
+        write!(io, "{}", sym)
     }
 }
 
@@ -147,5 +174,18 @@ mod test {
 
         assert_eq!("00:01:05", time);
         assert_eq!("01:00:00", hours);
+        assert_eq!("30:00:05", parse_duration(30 * 3600 + 5));
+    }
+
+    #[test]
+    fn write_report_or_remove_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.tsv");
+        let rows = vec![String::from("gene_1\tfailed")];
+        write_report_or_remove(&path, &rows, "locus\treason").unwrap();
+        let report = fs::read_to_string(&path).unwrap();
+        assert_eq!("locus\treason\ngene_1\tfailed\n", report);
+        write_report_or_remove(&path, &[], "locus\treason").unwrap();
+        assert!(!path.exists());
     }
 }
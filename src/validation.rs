@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use ansi_term::Colour::{Red, Yellow};
+use rayon::prelude::*;
+
+use crate::alignment::Alignment;
+use crate::error::{MyteError, Result};
+use crate::input::InputFmt;
+use crate::tree::{self, OutputDir};
+use crate::utils;
+
+// Characters IQ-TREE keeps in taxon names.
+// The rest are silently replaced with underscores.
+const IQTREE_NAME_CHARS: &str = "_-./|";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub locus: String,
+    pub severity: Severity,
+    pub taxon: Option<String>,
+    pub message: String,
+}
+
+// Checks every alignment before any analysis runs.
+// Returns the alignments left for the analyses. Loci with errors
+// abort the run, unless `exclude_invalid` leaves them out.
+pub fn validate_alignments(
+    paths: &[PathBuf],
+    input_fmt: &InputFmt,
+    outputs: &OutputDir,
+    exclude_invalid: bool,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut issues: Vec<Issue> = paths
+        .par_iter()
+        .flat_map(|path| check_locus(path, input_fmt))
+        .collect();
+    issues.sort_by(|a, b| a.locus.cmp(&b.locus));
+    let report = outputs.validation_report_file();
+    if !dry_run {
+        write_report(&report, &issues)?;
+    }
+    let invalid = get_invalid_loci(&issues);
+    print_validation_info(paths.len(), &issues, invalid.len(), &report);
+    if invalid.is_empty() {
+        return Ok(paths.to_vec());
+    }
+    if !exclude_invalid {
+        return Err(MyteError::Input(format!(
+            "Found {} invalid alignment(s). See {}. Use --exclude-invalid to leave them out",
+            invalid.len(),
+            report.display()
+        )));
+    }
+    log::warn!(
+        "{}: Excluded {} invalid alignment(s)\n",
+        Yellow.paint("WARNING"),
+        invalid.len()
+    );
+    Ok(paths
+        .iter()
+        .filter(|path| !invalid.contains(&tree::get_locus_name(path).as_ref()))
+        .cloned()
+        .collect())
+}
+
+fn check_locus(path: &Path, input_fmt: &InputFmt) -> Vec<Issue> {
+    let locus = tree::get_locus_name(path).to_string();
+    // Unequal lengths and duplicate names fail parsing.
+    match Alignment::from_file(path, input_fmt) {
+        Ok(aln) => check_alignment(&locus, &aln),
+        Err(err) => vec![Issue {
+            locus,
+            severity: Severity::Error,
+            taxon: None,
+            message: err.to_string(),
+        }],
+    }
+}
+
+fn check_alignment(locus: &str, aln: &Alignment) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    if aln.nchar() == 0 {
+        issues.push(Issue {
+            locus: String::from(locus),
+            severity: Severity::Error,
+            taxon: None,
+            message: String::from("Alignment has no sites"),
+        });
+    }
+    let mut add_issue = |severity, taxon: &str, message: String| {
+        issues.push(Issue {
+            locus: String::from(locus),
+            severity,
+            taxon: Some(String::from(taxon)),
+            message,
+        })
+    };
+    let mut renamed: HashMap<String, &str> = HashMap::new();
+    aln.taxa
+        .iter()
+        .zip(aln.sequences.iter())
+        .for_each(|(taxon, seq)| {
+            if let Some(c) = find_illegal_char(aln, seq) {
+                let msg = format!("Illegal character '{}' for {:?} data", c, aln.datatype);
+                add_issue(Severity::Error, taxon, msg);
+            }
+            if !seq.is_empty() && seq.chars().all(|c| aln.is_missing(c)) {
+                let msg = String::from("Sequence contains only gaps or missing data");
+                add_issue(Severity::Warning, taxon, msg);
+            }
+            let iqtree_name = get_iqtree_name(taxon);
+            if let Some(other) = renamed.get(&iqtree_name) {
+                let msg = format!("Becomes {} in IQ-TREE, same as {}", iqtree_name, other);
+                add_issue(Severity::Error, taxon, msg);
+            } else if &iqtree_name != taxon {
+                let msg = format!("IQ-TREE renames it to {}", iqtree_name);
+                add_issue(Severity::Warning, taxon, msg);
+            }
+            renamed.insert(iqtree_name, taxon);
+        });
+    issues
+}
+
+fn find_illegal_char(aln: &Alignment, seq: &str) -> Option<char> {
+    let symbols = aln.datatype.symbols();
+    seq.chars()
+        .find(|&c| c != aln.missing && c != aln.gap && !symbols.contains(c.to_ascii_uppercase()))
+}
+
+fn get_iqtree_name(taxon: &str) -> String {
+    taxon
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || IQTREE_NAME_CHARS.contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn get_invalid_loci(issues: &[Issue]) -> Vec<&str> {
+    let mut loci: Vec<&str> = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .map(|issue| issue.locus.as_str())
+        .collect();
+    loci.dedup();
+    loci
+}
+
+fn write_report(report: &Path, issues: &[Issue]) -> Result<()> {
+    let rows: Vec<String> = issues
+        .iter()
+        .map(|issue| {
+            let taxon = issue.taxon.as_deref().unwrap_or("NA");
+            format!(
+                "{}\t{}\t{}\t{}",
+                issue.locus, issue.severity, taxon, issue.message
+            )
+        })
+        .collect();
+    utils::write_report_or_remove(report, &rows, "locus\tseverity\ttaxon\tissue")
+}
+
+fn print_validation_info(num_aln: usize, issues: &[Issue], invalid: usize, report: &Path) {
+    let warnings = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Warning)
+        .count();
+    log::info!("{:18}: {}", "Alignments", num_aln);
+    log::info!("{:18}: {}", "Invalid loci", invalid);
+    log::info!("{:18}: {}", "Warnings", warnings);
+    if !issues.is_empty() {
+        log::info!("{:18}: {}", "Report", report.display());
+    }
+    if invalid > 0 {
+        log::error!(
+            "{}: {} alignment(s) failed validation",
+            Red.paint("ERROR"),
+            invalid
+        );
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+    use crate::alignment::DataType;
+
+    fn parse(content: &str) -> Alignment {
+        Alignment::parse(Path::new("a.fas"), content, &InputFmt::Fasta).unwrap()
+    }
+
+    #[test]
+    fn validation_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let paths = vec![
+            PathBuf::from("test_files/gene_1.nexus"),
+            PathBuf::from("test_files/gene_2.nexus"),
+        ];
+        let valid = validate_alignments(&paths, &InputFmt::Nexus, &outputs, false, false).unwrap();
+        assert_eq!(paths, valid);
+        assert!(!outputs.validation_report_file().exists());
+    }
+
+    #[test]
+    fn invalid_locus_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = OutputDir::new(dir.path());
+        let invalid = dir.path().join("gene_0.fas");
+        fs::write(&invalid, ">a\nACGT\n>a\nACGT\n").unwrap();
+        let valid = dir.path().join("gene_1.fas");
+        fs::write(&valid, ">a\nACGT\n>b\nAC-T\n").unwrap();
+        let paths = vec![invalid, valid.clone()];
+        let res = validate_alignments(&paths, &InputFmt::Fasta, &outputs, false, false);
+        assert!(matches!(res, Err(MyteError::Input(_))));
+        let kept = validate_alignments(&paths, &InputFmt::Fasta, &outputs, true, false).unwrap();
+        assert_eq!(vec![valid], kept);
+        let report = fs::read_to_string(outputs.validation_report_file()).unwrap();
+        assert_eq!(2, report.lines().count());
+        assert!(report
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("gene_0\terror\tNA\t"));
+    }
+
+    #[test]
+    fn check_alignment_test() {
+        let aln = parse(">a b\nACGT\n>c\nN--?\n>a_b\nACGT\n");
+        let issues = check_alignment("gene", &aln);
+        assert_eq!(3, issues.len());
+        assert_eq!(Severity::Warning, issues[0].severity);
+        assert_eq!("IQ-TREE renames it to a_b", issues[0].message);
+        assert_eq!(Some(String::from("c")), issues[1].taxon);
+        assert_eq!(Severity::Error, issues[2].severity);
+    }
+
+    #[test]
+    fn illegal_char_test() {
+        let mut aln = parse(">a\nACGT\n>b\nAC1T\n");
+        assert_eq!(DataType::Protein, aln.datatype);
+        assert_eq!(Some('1'), find_illegal_char(&aln, &aln.sequences[1]));
+        aln.datatype = DataType::Dna;
+        assert_eq!(Some('E'), find_illegal_char(&aln, "ACET"));
+        assert_eq!(None, find_illegal_char(&aln, "acgt"));
+    }
+}
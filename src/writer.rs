@@ -0,0 +1,318 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::alignment::{Alignment, DataType, PHYLIP_STRICT_NAME_LEN};
+use crate::error::{MyteError, Result};
+
+// NEXUS punctuation that requires quoting taxon names
+const NEXUS_PUNCTUATION: &str = "()[]{}/\\,;:=*'\"`<>^";
+// Block width of interleaved alignments without a line width
+const DEFAULT_LINE_WIDTH: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFmt {
+    Fasta,
+    Nexus,
+    Phylip,
+    PhylipStrict,
+}
+
+impl OutputFmt {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFmt::Fasta => "fas",
+            OutputFmt::Nexus => "nex",
+            OutputFmt::Phylip | OutputFmt::PhylipStrict => "phy",
+        }
+    }
+}
+
+// How sequences are laid out. By default, on a single line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Layout {
+    // Only for PHYLIP and NEXUS
+    pub interleaved: bool,
+    // Wraps FASTA sequences and sets the width of interleaved blocks
+    pub line_width: Option<usize>,
+}
+
+impl Layout {
+    // Sequential PHYLIP and NEXUS keep each sequence on a single line,
+    // so they are read back the same way.
+    fn block_width(&self, output_fmt: &OutputFmt) -> Option<usize> {
+        match output_fmt {
+            OutputFmt::Fasta => self.line_width,
+            _ if self.interleaved => Some(self.line_width.unwrap_or(DEFAULT_LINE_WIDTH)),
+            _ => None,
+        }
+    }
+}
+
+pub fn write_alignment(aln: &Alignment, path: &Path, output_fmt: &OutputFmt) -> Result<()> {
+    write_alignment_with(aln, path, output_fmt, &Layout::default())
+}
+
+pub fn write_alignment_with(
+    aln: &Alignment,
+    path: &Path,
+    output_fmt: &OutputFmt,
+    layout: &Layout,
+) -> Result<()> {
+    let blocks = get_blocks(aln, layout.block_width(output_fmt));
+    let res = match output_fmt {
+        OutputFmt::Fasta => write_fasta(aln, &blocks, path),
+        OutputFmt::Nexus => write_nexus(aln, &blocks, path),
+        OutputFmt::Phylip => write_phylip(aln, &aln.taxa, &blocks, path),
+        OutputFmt::PhylipStrict => {
+            let names = get_strict_names(aln)?;
+            write_phylip(aln, &names, &blocks, path)
+        }
+    };
+    res.map_err(|err| MyteError::io(path, err))
+}
+
+pub fn get_output_path(dir: &Path, name: &str, output_fmt: &OutputFmt) -> PathBuf {
+    dir.join(format!("{}.{}", name, output_fmt.extension()))
+}
+
+fn write_fasta(aln: &Alignment, blocks: &[Vec<&str>], path: &Path) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    aln.taxa
+        .iter()
+        .zip(blocks.iter())
+        .try_for_each(|(taxon, lines)| {
+            writeln!(writer, ">{}", taxon)?;
+            lines
+                .iter()
+                .try_for_each(|line| writeln!(writer, "{}", line))
+        })?;
+    writer.flush()
+}
+
+// Names are padded, so sequences line up. Interleaved blocks
+// after the first one only hold sequences.
+fn write_phylip(
+    aln: &Alignment,
+    names: &[String],
+    blocks: &[Vec<&str>],
+    path: &Path,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let width = get_name_width(names);
+    writeln!(writer, "{} {}", aln.ntax(), aln.nchar())?;
+    for block in 0..count_blocks(blocks) {
+        if block > 0 {
+            writeln!(writer)?;
+        }
+        names
+            .iter()
+            .zip(blocks.iter())
+            .try_for_each(|(name, lines)| {
+                if block == 0 {
+                    writeln!(writer, "{:width$} {}", name, lines[0], width = width)
+                } else {
+                    writeln!(writer, "{}", lines[block])
+                }
+            })?;
+    }
+    writer.flush()
+}
+
+fn write_nexus(aln: &Alignment, blocks: &[Vec<&str>], path: &Path) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let names: Vec<String> = aln.taxa.iter().map(|taxon| quote_name(taxon)).collect();
+    let width = get_name_width(&names);
+    let nblocks = count_blocks(blocks);
+    writeln!(writer, "#NEXUS")?;
+    writeln!(writer, "begin data;")?;
+    writeln!(
+        writer,
+        "\tdimensions ntax={} nchar={};",
+        aln.ntax(),
+        aln.nchar()
+    )?;
+    let interleave = if nblocks > 1 { " interleave" } else { "" };
+    writeln!(writer, "\tformat {}{};", get_nexus_format(aln), interleave)?;
+    writeln!(writer, "\tmatrix")?;
+    for block in 0..nblocks {
+        if block > 0 {
+            writeln!(writer)?;
+        }
+        names
+            .iter()
+            .zip(blocks.iter())
+            .try_for_each(|(name, lines)| {
+                writeln!(writer, "\t{:width$} {}", name, lines[block], width = width)
+            })?;
+    }
+    writeln!(writer, "\t;")?;
+    writeln!(writer, "end;")?;
+    writer.flush()
+}
+
+// Splits every sequence into lines of the given width.
+// All sequences have the same length, so they have the same number of lines.
+fn get_blocks(aln: &Alignment, width: Option<usize>) -> Vec<Vec<&str>> {
+    aln.sequences
+        .iter()
+        .map(|seq| match width {
+            Some(width) if width > 0 => split_sequence(seq, width),
+            _ => vec![seq.as_str()],
+        })
+        .collect()
+}
+
+fn split_sequence(seq: &str, width: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut rest = seq;
+    while !rest.is_empty() {
+        let end = rest
+            .char_indices()
+            .nth(width)
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        lines.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if lines.is_empty() {
+        lines.push(seq);
+    }
+    lines
+}
+
+fn count_blocks(blocks: &[Vec<&str>]) -> usize {
+    blocks.first().map(|lines| lines.len()).unwrap_or(0)
+}
+
+// Strict PHYLIP truncates names. Names that become
+// the same would no longer tell the taxa apart.
+fn get_strict_names(aln: &Alignment) -> Result<Vec<String>> {
+    let names: Vec<String> = aln
+        .taxa
+        .iter()
+        .map(|taxon| {
+            let name: String = taxon.chars().take(PHYLIP_STRICT_NAME_LEN).collect();
+            format!("{:width$}", name, width = PHYLIP_STRICT_NAME_LEN)
+        })
+        .collect();
+    let mut seen = HashSet::new();
+    match names.iter().position(|name| !seen.insert(name)) {
+        Some(i) => Err(MyteError::Input(format!(
+            "Taxon name {} in {} is the same as another name after truncating it to {} characters for strict PHYLIP",
+            aln.taxa[i],
+            aln.path.display(),
+            PHYLIP_STRICT_NAME_LEN
+        ))),
+        None => Ok(names),
+    }
+}
+
+fn get_nexus_format(aln: &Alignment) -> String {
+    let datatype = match aln.datatype {
+        DataType::Dna => "dna",
+        DataType::Rna => "rna",
+        DataType::Protein => "protein",
+        DataType::Standard => "standard symbols=\"0123456789\"",
+    };
+    format!(
+        "datatype={} missing={} gap={}",
+        datatype, aln.missing, aln.gap
+    )
+}
+
+fn quote_name(taxon: &str) -> String {
+    let needs_quotes = taxon
+        .chars()
+        .any(|c| c.is_whitespace() || NEXUS_PUNCTUATION.contains(c));
+    if needs_quotes {
+        format!("'{}'", taxon.replace('\'', "''"))
+    } else {
+        String::from(taxon)
+    }
+}
+
+fn get_name_width(names: &[String]) -> usize {
+    names.iter().map(|name| name.len()).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::InputFmt;
+
+    fn get_alignment() -> Alignment {
+        let content = ">taxon_a\nACGT\n>b\nAC-T\n";
+        Alignment::parse(Path::new("a.fas"), content, &InputFmt::Fasta).unwrap()
+    }
+
+    #[test]
+    fn write_alignment_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let aln = get_alignment();
+        [
+            OutputFmt::Nexus,
+            OutputFmt::Phylip,
+            OutputFmt::PhylipStrict,
+            OutputFmt::Fasta,
+        ]
+        .iter()
+        .for_each(|fmt| {
+            let path = get_output_path(dir.path(), "aln", fmt);
+            write_alignment(&aln, &path, fmt).unwrap();
+            let input_fmt = match fmt {
+                OutputFmt::Fasta => InputFmt::Fasta,
+                OutputFmt::Nexus => InputFmt::Nexus,
+                OutputFmt::Phylip => InputFmt::Phylip,
+                OutputFmt::PhylipStrict => InputFmt::PhylipStrict,
+            };
+            let copy = Alignment::from_file(&path, &input_fmt).unwrap();
+            assert_eq!(Some("AC-T"), copy.get_sequence("b"));
+            assert_eq!(aln.datatype, copy.datatype);
+        });
+    }
+
+    #[test]
+    fn interleaved_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let aln = get_alignment();
+        let layout = Layout {
+            interleaved: true,
+            line_width: Some(3),
+        };
+        let path = dir.path().join("aln.phy");
+        write_alignment_with(&aln, &path, &OutputFmt::Phylip, &layout).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!("2 4\ntaxon_a ACG\nb       AC-\n\nT\nT\n", content);
+        let copy = Alignment::from_file(&path, &InputFmt::Phylip).unwrap();
+        assert_eq!(aln.sequences, copy.sequences);
+
+        let path = dir.path().join("aln.nex");
+        write_alignment_with(&aln, &path, &OutputFmt::Nexus, &layout).unwrap();
+        let copy = Alignment::from_file(&path, &InputFmt::Nexus).unwrap();
+        assert_eq!(aln.sequences, copy.sequences);
+
+        let path = dir.path().join("aln.fas");
+        write_alignment_with(&aln, &path, &OutputFmt::Fasta, &layout).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(">taxon_a\nACG\nT\n>b\nAC-\nT\n", content);
+    }
+
+    #[test]
+    fn strict_names_test() {
+        let content = ">taxon_a_long_1\nACGT\n>taxon_a_long_2\nAC-T\n";
+        let aln = Alignment::parse(Path::new("a.fas"), content, &InputFmt::Fasta).unwrap();
+        assert!(matches!(get_strict_names(&aln), Err(MyteError::Input(_))));
+        assert_eq!(
+            vec!["taxon_a   ", "b         "],
+            get_strict_names(&get_alignment()).unwrap()
+        );
+    }
+
+    #[test]
+    fn quote_name_test() {
+        assert_eq!("'taxon a'", quote_name("taxon a"));
+        assert_eq!("taxon_a", quote_name("taxon_a"));
+    }
+}